@@ -0,0 +1,51 @@
+use image::ImageError;
+use std::io;
+use thiserror::Error;
+
+use crate::input::BindingsError;
+use crate::level::ascii::AsciiMapError;
+use crate::level::tiled::TiledError;
+use crate::level::tiles::TileRegistryError;
+
+/// Why a file the game needs could not be loaded. Every variant names the
+/// file at fault.
+#[derive(Debug, Error)]
+pub enum AssetError {
+    #[error("cannot read {path}: {source}")]
+    Io { path: String, source: io::Error },
+    #[error("cannot decode image {path}: {source}")]
+    Image { path: String, source: ImageError },
+    #[error("{0} has no tile images, point --tiles at a folder of images or a tilesheet")]
+    NoImages(String),
+    #[error("{path} is {width}x{height} but the other tiles are {expected_width}x{expected_height}, all tile images must be the same size")]
+    SizeMismatch {
+        path: String,
+        width: u32,
+        height: u32,
+        expected_width: u32,
+        expected_height: u32,
+    },
+    #[error("{path} is {width}x{height}, which does not divide into {tile_width}x{tile_height} tiles, check --tile-size")]
+    BadSheet {
+        path: String,
+        width: u32,
+        height: u32,
+        tile_width: u32,
+        tile_height: u32,
+    },
+    #[error("{count} tiles is more than the {max} texture layers this GPU supports")]
+    TooManyLayers { count: u32, max: u32 },
+    #[error("{0} is not a valid TrueType font")]
+    BadFont(String),
+    #[error("tile manifest {path}: {source}")]
+    TileManifest {
+        path: String,
+        source: TileRegistryError,
+    },
+    #[error("map {path}: {source}")]
+    AsciiMap { path: String, source: AsciiMapError },
+    #[error("map {path}: {source}")]
+    TiledMap { path: String, source: TiledError },
+    #[error("bindings {path}: {source}")]
+    Bindings { path: String, source: BindingsError },
+}
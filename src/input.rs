@@ -0,0 +1,200 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::Path;
+
+use glutin::event::{ModifiersState, MouseButton, VirtualKeyCode};
+use serde::Deserialize;
+use thiserror::Error;
+
+/// Something the player can do. The game reacts to actions rather than to
+/// keys, so any key can be bound to any action.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize)]
+pub enum Action {
+    MoveForward,
+    MoveBack,
+    TurnLeft,
+    TurnRight,
+    StrafeLeft,
+    StrafeRight,
+    /// Use the door in front, otherwise the stairs underfoot.
+    Interact,
+    /// Show what has been explored of the level.
+    OpenMap,
+}
+
+/// A key or mouse button.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Button {
+    Key(VirtualKeyCode),
+    Mouse(MouseButton),
+}
+
+#[derive(Debug, Error)]
+pub enum BindingsError {
+    #[error{"{0}"}]
+    IoError(#[from] std::io::Error),
+    #[error{"{0}"}]
+    JsonError(#[from] serde_json::Error),
+    #[error("{action:?} binding {binding}: unknown key, mouse button or modifier {name}")]
+    UnknownInput {
+        action: Action,
+        binding: String,
+        name: String,
+    },
+    #[error("{binding} is bound to both {first:?} and {second:?}")]
+    Conflict {
+        binding: String,
+        first: Action,
+        second: Action,
+    },
+}
+
+/// Which action each input triggers. The bindings file maps action names to
+/// lists of inputs: key names like `"W"` or `"Space"`, mouse buttons like
+/// `"Mouse:Left"` or `"Mouse:4"`, either of them after modifiers like
+/// `"Ctrl+M"`. Modifiers are `Ctrl`, `Shift`, `Alt` and `Logo`.
+pub struct Bindings {
+    actions: HashMap<(Button, ModifiersState), Action>,
+}
+
+impl Bindings {
+    pub fn load(path: &Path) -> Result<Bindings, BindingsError> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    fn parse(text: &str) -> Result<Bindings, BindingsError> {
+        let file: BTreeMap<Action, Vec<String>> = serde_json::from_str(text)?;
+        let mut actions = HashMap::new();
+        for (action, bindings) in file {
+            for binding in bindings {
+                let input = parse_input(&binding).map_err(|name| BindingsError::UnknownInput {
+                    action,
+                    binding: binding.clone(),
+                    name,
+                })?;
+                match actions.insert(input, action) {
+                    Some(first) if first != action => {
+                        return Err(BindingsError::Conflict {
+                            binding,
+                            first,
+                            second: action,
+                        })
+                    }
+                    _ => (),
+                }
+            }
+        }
+        Ok(Bindings { actions })
+    }
+
+    /// The action for `button` with `modifiers` held. An input bound without
+    /// modifiers still triggers while they are held, unless the combination
+    /// is bound to something else.
+    pub fn action(&self, button: Button, modifiers: ModifiersState) -> Option<Action> {
+        self.actions
+            .get(&(button, modifiers))
+            .or_else(|| self.actions.get(&(button, ModifiersState::empty())))
+            .copied()
+    }
+}
+
+/// Parses one input from a bindings file, or returns the part of it that
+/// names no key, mouse button or modifier.
+fn parse_input(binding: &str) -> Result<(Button, ModifiersState), String> {
+    let mut parts: Vec<&str> = binding.split('+').map(str::trim).collect();
+    // split always yields at least one part
+    let button = parts.pop().unwrap();
+    let mut modifiers = ModifiersState::empty();
+    for part in parts {
+        modifiers |= match part {
+            "Ctrl" => ModifiersState::CTRL,
+            "Shift" => ModifiersState::SHIFT,
+            "Alt" => ModifiersState::ALT,
+            "Logo" => ModifiersState::LOGO,
+            _ => return Err(part.to_string()),
+        };
+    }
+    let button = match button.strip_prefix("Mouse:") {
+        Some("Left") => Button::Mouse(MouseButton::Left),
+        Some("Right") => Button::Mouse(MouseButton::Right),
+        Some("Middle") => Button::Mouse(MouseButton::Middle),
+        Some(other) => match other.parse() {
+            Ok(n) => Button::Mouse(MouseButton::Other(n)),
+            Err(_) => return Err(button.to_string()),
+        },
+        // key names are the `VirtualKeyCode` variants
+        None => match serde_json::from_value(serde_json::Value::String(button.to_string())) {
+            Ok(key) => Button::Key(key),
+            Err(_) => return Err(button.to_string()),
+        },
+    };
+    Ok((button, modifiers))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_modifiers_and_buttons() {
+        assert_eq!(
+            parse_input("Ctrl+Shift+M"),
+            Ok((
+                Button::Key(VirtualKeyCode::M),
+                ModifiersState::CTRL | ModifiersState::SHIFT
+            ))
+        );
+        assert_eq!(
+            parse_input("Alt + Mouse:Left"),
+            Ok((Button::Mouse(MouseButton::Left), ModifiersState::ALT))
+        );
+        assert_eq!(
+            parse_input("Mouse:4"),
+            Ok((Button::Mouse(MouseButton::Other(4)), ModifiersState::empty()))
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_names() {
+        assert_eq!(parse_input("Hyper+W"), Err("Hyper".to_string()));
+        assert_eq!(parse_input("Ctrl+Nope"), Err("Nope".to_string()));
+        assert_eq!(parse_input("Mouse:Side"), Err("Mouse:Side".to_string()));
+        assert!(matches!(
+            Bindings::parse(r#"{"OpenMap": ["Ctrl+Nope"]}"#),
+            Err(BindingsError::UnknownInput { action: Action::OpenMap, name, .. }) if name == "Nope"
+        ));
+    }
+
+    #[test]
+    fn modifiers_fall_back_to_the_plain_input() {
+        let bindings = Bindings::parse(r#"{"MoveForward": ["W"], "OpenMap": ["Ctrl+W"]}"#).unwrap();
+        let w = Button::Key(VirtualKeyCode::W);
+        assert_eq!(bindings.action(w, ModifiersState::empty()), Some(Action::MoveForward));
+        assert_eq!(bindings.action(w, ModifiersState::SHIFT), Some(Action::MoveForward));
+        assert_eq!(bindings.action(w, ModifiersState::CTRL), Some(Action::OpenMap));
+        let s = Button::Key(VirtualKeyCode::S);
+        assert_eq!(bindings.action(s, ModifiersState::empty()), None);
+    }
+
+    #[test]
+    fn one_input_for_two_actions_is_a_conflict() {
+        assert!(matches!(
+            Bindings::parse(r#"{"MoveForward": ["W"], "OpenMap": ["W"]}"#),
+            Err(BindingsError::Conflict {
+                first: Action::MoveForward,
+                second: Action::OpenMap,
+                ..
+            })
+        ));
+        // the same input twice for one action is fine
+        assert!(Bindings::parse(r#"{"MoveForward": ["W", "W"]}"#).is_ok());
+    }
+
+    #[test]
+    fn default_bindings_load() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("assets/bindings.json");
+        let bindings = Bindings::load(&path).unwrap();
+        let space = Button::Key(VirtualKeyCode::Space);
+        assert_eq!(bindings.action(space, ModifiersState::empty()), Some(Action::Interact));
+    }
+}
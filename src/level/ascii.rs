@@ -0,0 +1,338 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::rc::Rc;
+
+use thiserror::Error;
+
+use super::door::DoorState;
+use super::level::{Level, TILE_DOOR, TILE_FLOOR, TILE_STAIRS_DOWN, TILE_STAIRS_UP, TILE_WALL};
+use super::tiles::TileRegistry;
+
+/// Symbols every map understands without a legend. A `[legend]` section can
+/// add to or override these.
+const DEFAULT_LEGEND: [(char, &str); 8] = [
+    ('#', "wall"),
+    ('.', "floor"),
+    ('+', "door"),
+    ('/', "open door"),
+    ('&', "locked door"),
+    ('<', "stairs_up"),
+    ('>', "stairs_down"),
+    ('@', "spawn"),
+];
+
+/// Symbols the exporter hands out to tiles the default legend has no symbol
+/// for.
+const EXPORT_SYMBOLS: &str =
+    "abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789$%*=?^~!|:;-_";
+
+#[derive(Debug, Error)]
+pub enum AsciiMapError {
+    #[error{"{0}"}]
+    IoError(#[from] std::io::Error),
+    #[error("line {line}: bad legend entry '{entry}', expected '<symbol> = <tile name|texture id|open door|locked door>', optionally prefixed with 'spawn'")]
+    BadLegend { line: usize, entry: String },
+    #[error("line {line}, column {column}: unknown symbol '{symbol}'")]
+    UnknownSymbol {
+        line: usize,
+        column: usize,
+        symbol: char,
+    },
+    #[error("line {line}, column {column}: second spawn, the first is on line {first_line}, column {first_column}")]
+    DuplicateSpawn {
+        line: usize,
+        column: usize,
+        first_line: usize,
+        first_column: usize,
+    },
+    #[error("line {line}: map has no spawn, mark one with '@'")]
+    MissingSpawn { line: usize },
+    #[error("map has no rows")]
+    EmptyMap,
+    #[error("level uses {0} different tiles, more than an ASCII map has symbols for")]
+    TooManySymbols(usize),
+}
+
+/// What a map cell turns into.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Cell {
+    Tile(u32),
+    Door(DoorState),
+}
+
+/// A legend entry: a cell, and whether the player starts there.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Symbol {
+    cell: Cell,
+    spawn: bool,
+}
+
+impl Symbol {
+    /// Parses a legend value: a tile name such as `wall` or `stairs_up`, or
+    /// a door state such as `locked door`, optionally after `spawn`. A bare
+    /// `spawn` is a spawn on floor. Maps saved before the tile registry give
+    /// other tiles as texture layers, such as `40`, which read as the tile
+    /// showing that texture, and spell the stairs `stairs up` and `stairs
+    /// down`; those still work.
+    fn parse(value: &str, tiles: &TileRegistry) -> Option<Self> {
+        let (spawn, value) = match value.strip_prefix("spawn") {
+            Some(rest) => (true, rest.trim()),
+            None => (false, value),
+        };
+        let cell = match value {
+            "" if spawn => Cell::Tile(TILE_FLOOR),
+            "door" => Cell::Door(DoorState::Closed),
+            "open door" => Cell::Door(DoorState::Open),
+            "locked door" => Cell::Door(DoorState::Locked),
+            "stairs up" => Cell::Tile(TILE_STAIRS_UP),
+            "stairs down" => Cell::Tile(TILE_STAIRS_DOWN),
+            name => match tiles.id(name) {
+                Some(id) => Cell::Tile(id),
+                None => match tiles.by_texture(name.parse().ok()?)? {
+                    // a door needs a state, and doors were closed by default
+                    TILE_DOOR => Cell::Door(DoorState::Closed),
+                    id => Cell::Tile(id),
+                },
+            },
+        };
+        Some(Self { cell, spawn })
+    }
+
+    /// The legend value `parse` reads back as this symbol.
+    fn describe(&self, tiles: &TileRegistry) -> String {
+        let cell = match self.cell {
+            Cell::Tile(id) => match tiles.get(id) {
+                Some(tile) => tile.name.clone(),
+                None => tiles.get(TILE_WALL).unwrap().name.clone(),
+            },
+            Cell::Door(DoorState::Closed) => "door".to_string(),
+            Cell::Door(DoorState::Open) => "open door".to_string(),
+            Cell::Door(DoorState::Locked) => "locked door".to_string(),
+        };
+        if self.spawn {
+            format!("spawn {}", cell)
+        } else {
+            cell
+        }
+    }
+}
+
+fn default_legend(tiles: &TileRegistry) -> HashMap<char, Symbol> {
+    DEFAULT_LEGEND
+        .iter()
+        .map(|&(c, value)| (c, Symbol::parse(value, tiles).unwrap()))
+        .collect()
+}
+
+impl Level {
+    /// Reads a level from an ASCII map file. See `from_ascii` for the format.
+    pub fn load_ascii(path: &Path, tiles: Rc<TileRegistry>) -> Result<Level, AsciiMapError> {
+        Level::from_ascii(&fs::read_to_string(path)?, tiles)
+    }
+
+    /// Builds a level from an ASCII map. The text is either just the map
+    /// rows, read with the default legend, or a `[legend]` section of
+    /// `<symbol> = <value>` lines followed by a `[map]` section:
+    ///
+    /// ```text
+    /// [legend]
+    /// ~ = rubble
+    /// [map]
+    /// #####
+    /// #@~>#
+    /// #####
+    /// ```
+    ///
+    /// Short rows are padded with wall. The map must have exactly one spawn.
+    pub fn from_ascii(text: &str, tiles: Rc<TileRegistry>) -> Result<Level, AsciiMapError> {
+        let mut legend = default_legend(&tiles);
+        let mut lines = text.lines().enumerate().map(|(i, line)| (i + 1, line));
+
+        let mut rows: Vec<(usize, &str)> = Vec::new();
+        match lines.clone().find(|(_, line)| !line.trim().is_empty()) {
+            Some((_, first)) if first.trim() == "[legend]" => {
+                lines.find(|(_, line)| line.trim() == "[legend]");
+                for (number, line) in lines.by_ref() {
+                    let entry = line.trim();
+                    if entry == "[map]" {
+                        break;
+                    }
+                    if entry.is_empty() {
+                        continue;
+                    }
+                    let mut chars = entry.chars();
+                    let symbol = chars.next().unwrap();
+                    let parsed = chars
+                        .as_str()
+                        .trim_start()
+                        .strip_prefix('=')
+                        .and_then(|value| Symbol::parse(value.trim(), &tiles));
+                    match parsed {
+                        Some(parsed) => legend.insert(symbol, parsed),
+                        None => {
+                            return Err(AsciiMapError::BadLegend {
+                                line: number,
+                                entry: entry.to_string(),
+                            })
+                        }
+                    };
+                }
+            }
+            Some((_, first)) if first.trim() == "[map]" => {
+                lines.find(|(_, line)| line.trim() == "[map]");
+            }
+            _ => {}
+        }
+        rows.extend(lines);
+        while rows.last().is_some_and(|(_, row)| row.trim().is_empty()) {
+            rows.pop();
+        }
+        if rows.is_empty() {
+            return Err(AsciiMapError::EmptyMap);
+        }
+
+        let width = rows.iter().map(|(_, row)| row.chars().count()).max().unwrap();
+        let mut level = Level::new(width as u32, rows.len() as u32, tiles);
+        level.fill(TILE_WALL);
+        level.metadata.generator = "ascii".to_string();
+
+        // doors go in last, their axis depends on the cells around them
+        let mut doors = Vec::new();
+        let mut spawn: Option<(usize, usize)> = None;
+        for (y, &(line, row)) in rows.iter().enumerate() {
+            for (x, c) in row.chars().enumerate() {
+                let column = x + 1;
+                let symbol = match legend.get(&c) {
+                    Some(symbol) => *symbol,
+                    None => {
+                        return Err(AsciiMapError::UnknownSymbol {
+                            line,
+                            column,
+                            symbol: c,
+                        })
+                    }
+                };
+                if symbol.spawn {
+                    if let Some((first_line, first_column)) = spawn {
+                        return Err(AsciiMapError::DuplicateSpawn {
+                            line,
+                            column,
+                            first_line,
+                            first_column,
+                        });
+                    }
+                    spawn = Some((line, column));
+                    level.spawn = [x as u32, y as u32];
+                }
+                match symbol.cell {
+                    Cell::Tile(tile) => level.set_value(x as u32, y as u32, tile),
+                    Cell::Door(state) => doors.push((x as i32, y as i32, state)),
+                }
+            }
+        }
+        if spawn.is_none() {
+            return Err(AsciiMapError::MissingSpawn { line: rows[0].0 });
+        }
+        for (x, y, state) in doors {
+            level.place_door(x, y, state);
+        }
+        Ok(level)
+    }
+
+    /// Writes the level to an ASCII map file that `load_ascii` reads back.
+    pub fn save_ascii(&self, path: &Path) -> Result<(), AsciiMapError> {
+        fs::write(path, self.to_ascii()?)?;
+        Ok(())
+    }
+
+    /// Renders the level as an ASCII map. Tiles the default legend has no
+    /// symbol for are given one in the `[legend]` section.
+    pub fn to_ascii(&self) -> Result<String, AsciiMapError> {
+        let mut symbols: Vec<(Symbol, char)> = DEFAULT_LEGEND
+            .iter()
+            .map(|&(c, value)| (Symbol::parse(value, self.tiles()).unwrap(), c))
+            .collect();
+        let mut spare = EXPORT_SYMBOLS
+            .chars()
+            .filter(|c| !DEFAULT_LEGEND.iter().any(|&(d, _)| d == *c));
+        let mut legend = String::new();
+
+        let mut map = String::new();
+        for y in 0..self.height() as i32 {
+            for x in 0..self.width() as i32 {
+                let cell = match self.door(x, y) {
+                    Some(door) => Cell::Door(door.state),
+                    None => Cell::Tile(self.get_value(x, y).unwrap()),
+                };
+                let symbol = Symbol {
+                    cell,
+                    spawn: [x as u32, y as u32] == self.spawn,
+                };
+                let c = match symbols.iter().find(|(s, _)| *s == symbol) {
+                    Some(&(_, c)) => c,
+                    None => {
+                        let c = spare
+                            .next()
+                            .ok_or(AsciiMapError::TooManySymbols(symbols.len() + 1))?;
+                        legend.push_str(&format!("{} = {}\n", c, symbol.describe(self.tiles())));
+                        symbols.push((symbol, c));
+                        c
+                    }
+                };
+                map.push(c);
+            }
+            map.push('\n');
+        }
+        Ok(format!("[legend]\n{}[map]\n{}", legend, map))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::level::tiles::test_registry;
+
+    #[test]
+    fn reads_maps_in_the_old_legend_format() {
+        // as exported before tiles had names: texture layers for tiles
+        // without a name, and spaced stairs
+        let map = "[legend]\n\
+                   a = 12\n\
+                   b = 40\n\
+                   c = 45\n\
+                   u = stairs up\n\
+                   d = spawn stairs down\n\
+                   [map]\n\
+                   bbbbbb\n\
+                   bdacu#\n\
+                   bbbbbb\n";
+        let level = Level::from_ascii(map, test_registry()).unwrap();
+        assert_eq!(level.spawn, [1, 1]);
+        assert_eq!(level.get_value(1, 1), Some(TILE_STAIRS_DOWN));
+        assert_eq!(level.get_value(2, 1), Some(TILE_FLOOR));
+        assert_eq!(level.get_value(3, 1), Some(TILE_DOOR));
+        assert_eq!(level.door(3, 1).map(|d| d.state), Some(DoorState::Closed));
+        assert_eq!(level.get_value(4, 1), Some(TILE_STAIRS_UP));
+        assert_eq!(level.get_value(0, 0), Some(TILE_WALL));
+    }
+
+    #[test]
+    fn rejects_ids_with_no_tile() {
+        let map = "[legend]\nx = 9999\n[map]\n#@x#\n";
+        assert!(matches!(
+            Level::from_ascii(map, test_registry()),
+            Err(AsciiMapError::BadLegend { line: 2, .. })
+        ));
+    }
+
+    #[test]
+    fn exported_maps_read_back() {
+        let tiles = test_registry();
+        let map = "#####\n#@.>#\n#+/&#\n#####\n";
+        let level = Level::from_ascii(map, tiles.clone()).unwrap();
+        let again = Level::from_ascii(&level.to_ascii().unwrap(), tiles).unwrap();
+        assert_eq!(again.to_ascii().unwrap(), level.to_ascii().unwrap());
+        assert_eq!(again.spawn, level.spawn);
+    }
+}
@@ -0,0 +1,201 @@
+use rand::Rng;
+
+use super::generator::LevelGenerator;
+use super::level::{Level, LevelRng, Rect, TILE_WALL};
+
+/// Tuning for the binary space partitioning generator.
+#[derive(Clone)]
+pub struct BspConfig {
+    /// Leaves are never split into parts smaller than this, in cells.
+    pub min_leaf_size: i32,
+    /// Smallest room dimension, walls included.
+    pub min_room_size: i32,
+    /// Largest room dimension, walls included.
+    pub max_room_size: i32,
+    /// Range the split point is picked from, as a fraction of the leaf's
+    /// length along the split axis.
+    pub split_ratio: (f32, f32),
+    /// Stop splitting after this many levels of recursion.
+    pub max_depth: u32,
+}
+
+impl Default for BspConfig {
+    fn default() -> Self {
+        Self {
+            min_leaf_size: 8,
+            min_room_size: 5,
+            max_room_size: 12,
+            split_ratio: (0.4, 0.6),
+            max_depth: 5,
+        }
+    }
+}
+
+/// A node of the partition tree. Leaves hold a room, inner nodes hold the
+/// two halves they were split into.
+struct Node {
+    area: Rect,
+    children: Option<Box<(Node, Node)>>,
+    room: Option<Rect>,
+}
+
+impl Node {
+    fn leaf(area: Rect) -> Self {
+        Self {
+            area,
+            children: None,
+            room: None,
+        }
+    }
+
+    /// Collects the rooms of every leaf under this node.
+    fn rooms(&self, out: &mut Vec<Rect>) {
+        if let Some(room) = self.room {
+            out.push(room);
+        }
+        if let Some(children) = &self.children {
+            children.0.rooms(out);
+            children.1.rooms(out);
+        }
+    }
+}
+
+/// Builds the level by recursively splitting the map, placing one room in
+/// each leaf and joining sibling subtrees with corridors.
+#[derive(Clone, Default)]
+pub struct BspGenerator {
+    pub config: BspConfig,
+}
+
+impl LevelGenerator for BspGenerator {
+    fn name(&self) -> &str {
+        "bsp"
+    }
+
+    fn generate(&self, level: &mut Level, rng: &mut LevelRng) {
+        let config = &self.config;
+        level.fill(TILE_WALL);
+
+        let width = level.width() as i32;
+        let height = level.height() as i32;
+        if width - 2 < config.min_room_size || height - 2 < config.min_room_size {
+            log::warn!("level {}x{} is too small for rooms", width, height);
+            return;
+        }
+
+        // keep a one-cell border of solid wall around the map
+        let mut root = Node::leaf(Rect::new(1, 1, width - 2, height - 2));
+        split(&mut root, 0, config, rng);
+        place_rooms(level, &mut root, config, rng);
+        connect(level, &root, rng);
+
+        let mut rooms = Vec::new();
+        root.rooms(&mut rooms);
+        log::info!("bsp placed {} rooms", rooms.len());
+        if let Some(room) = rooms.first() {
+            let (spawn_x, spawn_y) = room.center();
+            level.spawn = [spawn_x as u32, spawn_y as u32];
+        }
+        level.place_room_doors(&rooms, rng);
+        level.metadata.rooms = rooms;
+    }
+}
+
+fn split(node: &mut Node, depth: u32, config: &BspConfig, rng: &mut LevelRng) {
+    if depth >= config.max_depth {
+        return;
+    }
+
+    let area = node.area;
+    let w = area.x2 - area.x1 + 1;
+    let h = area.y2 - area.y1 + 1;
+    let can_split_x = w >= config.min_leaf_size * 2;
+    let can_split_y = h >= config.min_leaf_size * 2;
+
+    // prefer cutting across the long side so leaves stay roughly square
+    let split_x = match (can_split_x, can_split_y) {
+        (false, false) => return,
+        (true, false) => true,
+        (false, true) => false,
+        (true, true) => {
+            if w as f32 > h as f32 * 1.25 {
+                true
+            } else if h as f32 > w as f32 * 1.25 {
+                false
+            } else {
+                rng.gen_bool(0.5)
+            }
+        }
+    };
+
+    let length = if split_x { w } else { h };
+    let (lo, hi) = config.split_ratio;
+    let ratio = if hi > lo { rng.gen_range(lo..hi) } else { lo };
+    let cut = ((length as f32 * ratio) as i32)
+        .max(config.min_leaf_size)
+        .min(length - config.min_leaf_size);
+
+    let (a, b) = if split_x {
+        (
+            Rect::new(area.x1, area.y1, cut, h),
+            Rect::new(area.x1 + cut, area.y1, w - cut, h),
+        )
+    } else {
+        (
+            Rect::new(area.x1, area.y1, w, cut),
+            Rect::new(area.x1, area.y1 + cut, w, h - cut),
+        )
+    };
+
+    let mut children = Box::new((Node::leaf(a), Node::leaf(b)));
+    split(&mut children.0, depth + 1, config, rng);
+    split(&mut children.1, depth + 1, config, rng);
+    node.children = Some(children);
+}
+
+fn place_rooms(level: &mut Level, node: &mut Node, config: &BspConfig, rng: &mut LevelRng) {
+    if let Some(children) = &mut node.children {
+        place_rooms(level, &mut children.0, config, rng);
+        place_rooms(level, &mut children.1, config, rng);
+        return;
+    }
+
+    let area = node.area;
+    let w = area.x2 - area.x1 + 1;
+    let h = area.y2 - area.y1 + 1;
+    if w < config.min_room_size || h < config.min_room_size {
+        return;
+    }
+
+    let max_w = config.max_room_size.min(w).max(config.min_room_size);
+    let max_h = config.max_room_size.min(h).max(config.min_room_size);
+    let room_w = rng.gen_range(config.min_room_size..=max_w);
+    let room_h = rng.gen_range(config.min_room_size..=max_h);
+    let x = rng.gen_range(area.x1..=area.x2 - room_w + 1);
+    let y = rng.gen_range(area.y1..=area.y2 - room_h + 1);
+    let room = Rect::new(x, y, room_w, room_h);
+    level.carve_room(&room);
+    node.room = Some(room);
+}
+
+/// Joins the two halves of every inner node, bottom up, by linking a random
+/// room from each side.
+fn connect(level: &mut Level, node: &Node, rng: &mut LevelRng) {
+    let children = match &node.children {
+        Some(children) => children,
+        None => return,
+    };
+    connect(level, &children.0, rng);
+    connect(level, &children.1, rng);
+
+    let mut left = Vec::new();
+    let mut right = Vec::new();
+    children.0.rooms(&mut left);
+    children.1.rooms(&mut right);
+    if left.is_empty() || right.is_empty() {
+        return;
+    }
+    let a = left[rng.gen_range(0..left.len() as u32) as usize];
+    let b = right[rng.gen_range(0..right.len() as u32) as usize];
+    level.carve_l_corridor(a.center(), b.center(), rng);
+}
@@ -0,0 +1,132 @@
+use rand::Rng;
+
+use super::generator::LevelGenerator;
+use super::grid::Grid;
+use super::level::{Level, LevelRng, TILE_FLOOR, TILE_WALL};
+
+/// Tuning for the cellular automata cave generator.
+#[derive(Clone)]
+pub struct CaveConfig {
+    /// Chance that a cell starts out as wall in the initial noise.
+    pub fill_chance: f64,
+    /// An open cell turns into wall when at least this many of its eight
+    /// neighbours are walls.
+    pub birth_limit: u32,
+    /// A wall cell stays a wall when at least this many of its eight
+    /// neighbours are walls.
+    pub survival_limit: u32,
+    /// Number of smoothing passes run over the noise.
+    pub smoothing_passes: u32,
+}
+
+impl Default for CaveConfig {
+    fn default() -> Self {
+        Self {
+            fill_chance: 0.45,
+            birth_limit: 5,
+            survival_limit: 4,
+            smoothing_passes: 5,
+        }
+    }
+}
+
+/// Builds an organic cave: random noise smoothed by birth/survival passes,
+/// with every pocket outside the largest open region filled in.
+#[derive(Clone, Default)]
+pub struct CaveGenerator {
+    pub config: CaveConfig,
+}
+
+impl LevelGenerator for CaveGenerator {
+    fn name(&self) -> &str {
+        "cave"
+    }
+
+    fn generate(&self, level: &mut Level, rng: &mut LevelRng) {
+        let config = &self.config;
+        let width = level.width() as i32;
+        let height = level.height() as i32;
+
+        // 1) Seed noise, keeping the outer border solid
+        let mut walls = Grid::new(level.width(), level.height(), true);
+        for y in 1..height - 1 {
+            for x in 1..width - 1 {
+                *walls.get_mut(x, y).unwrap() = rng.gen_bool(config.fill_chance);
+            }
+        }
+
+        // 2) Smooth it into caves
+        for _ in 0..config.smoothing_passes {
+            let mut next = walls.clone();
+            for y in 1..height - 1 {
+                for x in 1..width - 1 {
+                    let count = count_wall_neighbours(&walls, x, y);
+                    let wall = if walls.get(x, y) == Some(&true) {
+                        count >= config.survival_limit
+                    } else {
+                        count >= config.birth_limit
+                    };
+                    *next.get_mut(x, y).unwrap() = wall;
+                }
+            }
+            walls = next;
+        }
+
+        for y in 0..height {
+            for x in 0..width {
+                let tile = if walls.get(x, y) == Some(&true) {
+                    TILE_WALL
+                } else {
+                    TILE_FLOOR
+                };
+                level.set_value(x as u32, y as u32, tile);
+            }
+        }
+
+        // 3) Fill every pocket the player couldn't reach from the main cave
+        let regions = level.floor_regions();
+        for region in regions.iter().skip(1) {
+            for &(x, y) in region {
+                level.set_value(x as u32, y as u32, TILE_WALL);
+            }
+        }
+
+        // 4) Spawn in the largest region, preferably away from walls
+        let largest = match regions.first() {
+            Some(region) => region,
+            None => {
+                log::warn!("cave generation produced no open cells");
+                return;
+            }
+        };
+        log::info!(
+            "cave has {} open cells, filled {} pockets",
+            largest.len(),
+            regions.len() - 1
+        );
+        let open: Vec<&(i32, i32)> = largest
+            .iter()
+            .filter(|&&(x, y)| count_wall_neighbours(&walls, x, y) == 0)
+            .collect();
+        let (spawn_x, spawn_y) = if open.is_empty() {
+            largest[rng.gen_range(0..largest.len() as u32) as usize]
+        } else {
+            *open[rng.gen_range(0..open.len() as u32) as usize]
+        };
+        level.spawn = [spawn_x as u32, spawn_y as u32];
+    }
+}
+
+/// Counts the walls among the eight neighbours of (x, y). Cells outside the
+/// grid count as walls.
+fn count_wall_neighbours(walls: &Grid<bool>, x: i32, y: i32) -> u32 {
+    let mut count = 0;
+    for dy in -1..=1 {
+        for dx in -1..=1 {
+            if (dx != 0 || dy != 0) && *walls.get(x + dx, y + dy).unwrap_or(&true) {
+                count += 1;
+            }
+        }
+    }
+    count
+}
@@ -0,0 +1,352 @@
+use std::collections::VecDeque;
+
+use super::door::DoorState;
+use super::generator::LevelGenerator;
+use super::grid::Grid;
+use super::level::{Level, LevelRng, TILE_FLOOR};
+
+/// Which floor cells can be walked to from the spawn point. A locked door
+/// counts as a wall until one of its keys can be reached.
+#[derive(Debug, Clone)]
+pub struct ConnectivityReport {
+    /// Number of floor cells reachable from the spawn.
+    pub reachable_cells: usize,
+    /// Floor regions that can't be reached from the spawn, largest first.
+    pub unreachable_regions: Vec<Vec<(i32, i32)>>,
+    /// Indices into `LevelMetadata::rooms` of rooms the spawn can't reach.
+    pub unreachable_rooms: Vec<usize>,
+    /// Times the level was generated, 0 if it wasn't by `generate_connected`.
+    pub attempts: u32,
+    /// Whether corridors were carved, i.e. no attempt came out connected.
+    pub repaired: bool,
+}
+
+impl ConnectivityReport {
+    /// True when every floor cell can be reached from the spawn.
+    pub fn is_connected(&self) -> bool {
+        self.unreachable_regions.is_empty()
+    }
+
+    pub fn unreachable_cells(&self) -> usize {
+        self.unreachable_regions.iter().map(|r| r.len()).sum()
+    }
+}
+
+/// What to do with a generated level that has unreachable floor.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConnectivityPolicy {
+    /// Carve corridors from each unreachable region to the reachable one.
+    Repair,
+    /// Throw the level away and generate again, up to `max_attempts` times
+    /// in total. Falls back to repairing the last attempt.
+    Regenerate { max_attempts: u32 },
+}
+
+impl Level {
+    /// Flood fills from the spawn and reports every floor region it misses.
+    pub fn connectivity(&self) -> ConnectivityReport {
+        let (reached, reachable_cells) = self.reachable();
+        let unreachable_regions = self.regions(|x, y| reached.get(x, y) == Some(&false));
+        let unreachable_rooms = self
+            .metadata
+            .rooms
+            .iter()
+            .enumerate()
+            .filter(|(_, room)| {
+                // a room counts as reached if any of its interior is
+                !(room.y1 + 1..room.y2)
+                    .any(|y| (room.x1 + 1..room.x2).any(|x| reached.get(x, y) == Some(&true)))
+            })
+            .map(|(i, _)| i)
+            .collect();
+
+        ConnectivityReport {
+            reachable_cells,
+            unreachable_regions,
+            unreachable_rooms,
+            attempts: 0,
+            repaired: false,
+        }
+    }
+
+    /// The traversable cells the spawn can reach, and how many there are.
+    /// The fill stops at locked doors, and goes on through one once it has
+    /// reached a cell of any of the door's keys.
+    fn reachable(&self) -> (Grid<bool>, usize) {
+        let mut reached = Grid::new(self.width(), self.height(), false);
+        let spawn = (self.spawn[0] as i32, self.spawn[1] as i32);
+        if !self.is_traversable(spawn.0, spawn.1) {
+            return (reached, 0);
+        }
+        *reached.get_mut(spawn.0, spawn.1).unwrap() = true;
+        let mut stack = vec![spawn];
+        let mut locked = Vec::new();
+        let mut count = 0;
+        while !stack.is_empty() {
+            while let Some((x, y)) = stack.pop() {
+                count += 1;
+                for (nx, ny) in [(x + 1, y), (x - 1, y), (x, y + 1), (x, y - 1)] {
+                    if !self.is_traversable(nx, ny) || reached.get(nx, ny) != Some(&false) {
+                        continue;
+                    }
+                    if self.door(nx, ny).map(|d| d.state) == Some(DoorState::Locked) {
+                        locked.push((nx, ny));
+                        continue;
+                    }
+                    *reached.get_mut(nx, ny).unwrap() = true;
+                    stack.push((nx, ny));
+                }
+            }
+            locked.retain(|&(x, y)| {
+                let unlocked = self.keys_to(x, y).any(|key| {
+                    let area = key.area;
+                    (area.y1..=area.y2)
+                        .any(|ky| (area.x1..=area.x2).any(|kx| reached.get(kx, ky) == Some(&true)))
+                });
+                if unlocked && reached.get(x, y) == Some(&false) {
+                    *reached.get_mut(x, y).unwrap() = true;
+                    stack.push((x, y));
+                }
+                !unlocked
+            });
+        }
+        (reached, count)
+    }
+
+    /// Carves the shortest corridor from every unreachable region to floor
+    /// the spawn can reach. Returns the report for the repaired level.
+    pub fn repair_connectivity(&mut self) -> ConnectivityReport {
+        let mut report = self.connectivity();
+        if report.reachable_cells == 0 {
+            log::warn!("spawn {:?} is not on floor, cannot repair", self.spawn);
+            return report;
+        }
+
+        let mut carved = 0;
+        while !report.is_connected() {
+            let region = &report.unreachable_regions[0];
+            match self.path_to_reachable(region) {
+                Some(path) => {
+                    for (x, y) in path {
+                        // a locked door in the way is unlocked, not dug round
+                        if !self.unlock_door(x, y) && !self.is_traversable(x, y) {
+                            self.set_value(x as u32, y as u32, TILE_FLOOR);
+                        }
+                    }
+                    carved += 1;
+                }
+                None => {
+                    log::warn!("no route out of region at {:?}", region[0]);
+                    break;
+                }
+            }
+            report = self.connectivity();
+        }
+        log::info!("carved {} repair corridors", carved);
+        report.repaired = carved > 0;
+        report
+    }
+
+    /// Breadth first search through walls from every cell of `region` to the
+    /// nearest cell reachable from the spawn, keeping off the outer border.
+    fn path_to_reachable(&self, region: &[(i32, i32)]) -> Option<Vec<(i32, i32)>> {
+        let (reached, _) = self.reachable();
+
+        let mut came_from: Grid<Option<(i32, i32)>> = Grid::new(self.width(), self.height(), None);
+        let mut visited = Grid::new(self.width(), self.height(), false);
+        let mut queue = VecDeque::new();
+        for &(x, y) in region {
+            *visited.get_mut(x, y).unwrap() = true;
+            queue.push_back((x, y));
+        }
+
+        let inner = |x: i32, y: i32| {
+            x > 0 && y > 0 && x < self.width() as i32 - 1 && y < self.height() as i32 - 1
+        };
+        while let Some((x, y)) = queue.pop_front() {
+            if reached.get(x, y) == Some(&true) {
+                let mut path = Vec::new();
+                let mut cell = Some((x, y));
+                while let Some((cx, cy)) = cell {
+                    path.push((cx, cy));
+                    cell = *came_from.get(cx, cy).unwrap();
+                }
+                return Some(path);
+            }
+            for (nx, ny) in [(x + 1, y), (x - 1, y), (x, y + 1), (x, y - 1)] {
+                if !inner(nx, ny) || visited.get(nx, ny) != Some(&false) {
+                    continue;
+                }
+                *visited.get_mut(nx, ny).unwrap() = true;
+                *came_from.get_mut(nx, ny).unwrap() = Some((x, y));
+                queue.push_back((nx, ny));
+            }
+        }
+        None
+    }
+
+    /// Generates the level and makes sure the spawn can reach all of it.
+    pub fn generate_connected(
+        &mut self,
+        generator: &dyn LevelGenerator,
+        rng: &mut LevelRng,
+        policy: ConnectivityPolicy,
+    ) -> ConnectivityReport {
+        let attempts = match policy {
+            ConnectivityPolicy::Repair => 1,
+            ConnectivityPolicy::Regenerate { max_attempts } => max_attempts.max(1),
+        };
+        for attempt in 1..=attempts {
+            self.generate(generator, rng);
+            let mut report = self.connectivity();
+            report.attempts = attempt;
+            if report.is_connected() {
+                return report;
+            }
+            log::info!(
+                "attempt {}: {} unreachable cells in {} regions, rooms {:?} cut off",
+                attempt,
+                report.unreachable_cells(),
+                report.unreachable_regions.len(),
+                report.unreachable_rooms
+            );
+        }
+        let mut report = self.repair_connectivity();
+        report.attempts = attempts;
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::level::generator::GeneratorRegistry;
+    use crate::level::level::{Rect, TILE_WALL};
+    use crate::level::tiles::test_registry;
+    use crate::level::trigger::Trigger;
+
+    /// Two rooms with no way between them, spawning in the left one.
+    struct SplitRooms {
+        runs: Cell<u32>,
+    }
+
+    impl LevelGenerator for SplitRooms {
+        fn name(&self) -> &str {
+            "split"
+        }
+
+        fn generate(&self, level: &mut Level, _rng: &mut LevelRng) {
+            self.runs.set(self.runs.get() + 1);
+            level.fill(TILE_WALL);
+            level.carve_room(&Rect::new(1, 1, 5, 5));
+            level.carve_room(&Rect::new(8, 1, 5, 5));
+            level.spawn = [3, 3];
+        }
+    }
+
+    fn split_rooms() -> SplitRooms {
+        SplitRooms { runs: Cell::new(0) }
+    }
+
+    #[test]
+    fn repair_joins_disjoint_rooms() {
+        let mut level = Level::new(14, 7, test_registry());
+        level.generate(&split_rooms(), &mut LevelRng::seed_from_u64(0));
+        let before = level.connectivity();
+        assert_eq!(before.unreachable_regions.len() + 1, 2);
+        assert!(!before.is_connected());
+
+        let after = level.repair_connectivity();
+        assert!(after.is_connected());
+        assert!(after.repaired);
+        assert_eq!(level.floor_regions().len(), 1);
+        // both rooms plus the corridor between them
+        assert!(after.reachable_cells > before.reachable_cells + before.unreachable_cells());
+    }
+
+    #[test]
+    fn regenerate_stops_at_max_attempts() {
+        let generator = split_rooms();
+        let mut level = Level::new(14, 7, test_registry());
+        let report = level.generate_connected(
+            &generator,
+            &mut LevelRng::seed_from_u64(0),
+            ConnectivityPolicy::Regenerate { max_attempts: 3 },
+        );
+        assert_eq!(generator.runs.get(), 3);
+        assert_eq!(report.attempts, 3);
+        // no attempt was connected, so it fell back to repairing
+        assert!(report.repaired);
+    }
+
+    #[test]
+    fn builtin_generators_make_connected_levels() {
+        let registry = GeneratorRegistry::with_builtins();
+        for name in registry.names() {
+            let mut level = Level::new(64, 48, test_registry());
+            let report = level.generate_connected(
+                registry.get(name).unwrap(),
+                &mut LevelRng::seed_from_u64(7),
+                ConnectivityPolicy::Repair,
+            );
+            assert!(report.is_connected(), "{} left cells unreachable", name);
+            assert_eq!(level.floor_regions().len(), 1, "{}", name);
+        }
+    }
+
+    #[test]
+    fn locked_doors_need_a_reachable_key() {
+        let mut level = Level::from_ascii("#######\n#@.&..#\n#######\n", test_registry()).unwrap();
+        let report = level.connectivity();
+        assert_eq!(report.unreachable_regions, vec![vec![(3, 1), (4, 1), (5, 1)]]);
+
+        // a key the fill can't reach doesn't help
+        level.add_trigger(Trigger::key(5, 1, (3, 1)));
+        assert!(!level.connectivity().is_connected());
+        level.add_trigger(Trigger::key(2, 1, (3, 1)));
+        assert!(level.connectivity().is_connected());
+
+        assert_eq!(level.unlock_from(2, 1), vec![(3, 1)]);
+        assert_eq!(level.door(3, 1).map(|d| d.state), Some(DoorState::Closed));
+        assert!(level.unlock_from(2, 1).is_empty());
+    }
+
+    #[test]
+    fn generated_locked_rooms_come_with_keys() {
+        let generators = GeneratorRegistry::with_builtins();
+        let (mut level, report) = (0..100)
+            .find_map(|seed| {
+                let mut level = Level::new(64, 48, test_registry());
+                let report = level.generate_connected(
+                    generators.get("bsp").unwrap(),
+                    &mut LevelRng::seed_from_u64(seed),
+                    ConnectivityPolicy::Repair,
+                );
+                let locked = level.doors.values().any(|d| d.state == DoorState::Locked);
+                Some((level, report)).filter(|(_, report)| locked && !report.repaired)
+            })
+            .expect("no seed gave a locked room");
+        assert!(report.is_connected());
+        let locked: Vec<(i32, i32)> = level
+            .doors
+            .iter()
+            .filter(|(_, d)| d.state == DoorState::Locked)
+            .map(|(&cell, _)| cell)
+            .collect();
+        for &(x, y) in &locked {
+            assert_eq!(level.keys_to(x, y).count(), 1);
+        }
+
+        // without its key a locked room is cut off, and repair unlocks it
+        level.triggers.clear();
+        let report = level.connectivity();
+        assert!(!report.is_connected());
+        assert!(!report.unreachable_rooms.is_empty());
+        assert!(level.repair_connectivity().is_connected());
+        assert!(locked.iter().all(|&(x, y)| level.door(x, y).unwrap().state == DoorState::Closed));
+    }
+}
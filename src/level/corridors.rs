@@ -0,0 +1,96 @@
+use rand::Rng;
+
+use super::level::{Level, LevelRng, Rect};
+
+/// Corridor length between two rooms, measured the way L-shaped corridors
+/// run: along the axes between their centers.
+fn distance(a: &Rect, b: &Rect) -> i32 {
+    let (ax, ay) = a.center();
+    let (bx, by) = b.center();
+    (ax - bx).abs() + (ay - by).abs()
+}
+
+/// Builds a minimum spanning tree over the room centers with Prim's
+/// algorithm. Returns the tree's edges as pairs of room indices.
+pub fn spanning_edges(rooms: &[Rect]) -> Vec<(usize, usize)> {
+    let mut edges = Vec::new();
+    if rooms.is_empty() {
+        return edges;
+    }
+
+    let mut in_tree = vec![false; rooms.len()];
+    // cheapest known link from each room into the tree: (distance, tree room)
+    let mut best: Vec<Option<(i32, usize)>> = vec![None; rooms.len()];
+    let mut current = 0;
+    in_tree[0] = true;
+    for _ in 1..rooms.len() {
+        for (i, room) in rooms.iter().enumerate() {
+            if in_tree[i] {
+                continue;
+            }
+            let d = distance(&rooms[current], room);
+            let closer = match best[i] {
+                Some((bd, _)) => d < bd,
+                None => true,
+            };
+            if closer {
+                best[i] = Some((d, current));
+            }
+        }
+        let next = (0..rooms.len())
+            .filter(|&i| !in_tree[i])
+            .min_by_key(|&i| best[i].map_or(i32::MAX, |(d, _)| d))
+            .unwrap();
+        let (_, from) = best[next].unwrap();
+        edges.push((from, next));
+        in_tree[next] = true;
+        current = next;
+    }
+    edges
+}
+
+/// Picks extra edges that close loops in the tree: each room gets a
+/// `loop_chance` chance of also linking to its nearest room it isn't
+/// already joined to.
+pub fn loop_edges(
+    rooms: &[Rect],
+    tree: &[(usize, usize)],
+    loop_chance: f64,
+    rng: &mut LevelRng,
+) -> Vec<(usize, usize)> {
+    let linked = |edges: &[(usize, usize)], a: usize, b: usize| {
+        edges.iter().any(|&(x, y)| (x, y) == (a, b) || (x, y) == (b, a))
+    };
+
+    let mut extra: Vec<(usize, usize)> = Vec::new();
+    for (i, room) in rooms.iter().enumerate() {
+        if !rng.gen_bool(loop_chance) {
+            continue;
+        }
+        let nearest = (0..rooms.len())
+            .filter(|&j| j != i && !linked(tree, i, j) && !linked(&extra, i, j))
+            .min_by_key(|&j| distance(room, &rooms[j]));
+        if let Some(j) = nearest {
+            extra.push((i, j));
+        }
+    }
+    extra
+}
+
+impl Level {
+    /// Joins `rooms` with a minimum spanning tree of L-shaped corridors, plus
+    /// a few loop corridors so there is more than one way around.
+    pub fn connect_rooms(&mut self, rooms: &[Rect], loop_chance: f64, rng: &mut LevelRng) {
+        let tree = spanning_edges(rooms);
+        let loops = loop_edges(rooms, &tree, loop_chance, rng);
+        log::info!(
+            "connecting {} rooms with {} corridors and {} loops",
+            rooms.len(),
+            tree.len(),
+            loops.len()
+        );
+        for (a, b) in tree.into_iter().chain(loops) {
+            self.carve_l_corridor(rooms[a].center(), rooms[b].center(), rng);
+        }
+    }
+}
@@ -0,0 +1,155 @@
+use rand::Rng;
+
+use super::level::{Level, LevelRng, Rect, TILE_DOOR, TILE_FLOOR, TILE_WALL};
+use super::trigger::Trigger;
+
+/// Chance that the only door into a dead-end room is locked.
+const LOCKED_DOOR_CHANCE: f64 = 0.25;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DoorState {
+    Open,
+    Closed,
+    Locked,
+}
+
+/// Which way the passage through a door runs.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DoorAxis {
+    /// The passage runs along x, so the closed slab spans the y axis.
+    X,
+    /// The passage runs along y, so the closed slab spans the x axis.
+    Y,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Door {
+    pub state: DoorState,
+    pub axis: DoorAxis,
+}
+
+/// What happened when the player used a door.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DoorEvent {
+    Opened,
+    Closed,
+    Locked,
+}
+
+impl Level {
+    /// Turns (x, y) into a door. The passage axis is taken from the
+    /// surrounding walls: a door with walls above and below lets you
+    /// through along x.
+    pub fn place_door(&mut self, x: i32, y: i32, state: DoorState) {
+        let axis = if self.is_traversable(x, y - 1) || self.is_traversable(x, y + 1) {
+            DoorAxis::Y
+        } else {
+            DoorAxis::X
+        };
+        self.set_value(x as u32, y as u32, TILE_DOOR);
+        self.doors.insert((x, y), Door { state, axis });
+    }
+
+    pub fn door(&self, x: i32, y: i32) -> Option<&Door> {
+        self.doors.get(&(x, y))
+    }
+
+    /// Opens or closes the door at (x, y). Returns `None` if there is no
+    /// door there. Locked doors stay shut until unlocked.
+    pub fn use_door(&mut self, x: i32, y: i32) -> Option<DoorEvent> {
+        let door = self.doors.get_mut(&(x, y))?;
+        let event = match door.state {
+            DoorState::Open => {
+                door.state = DoorState::Closed;
+                DoorEvent::Closed
+            }
+            DoorState::Closed => {
+                door.state = DoorState::Open;
+                DoorEvent::Opened
+            }
+            DoorState::Locked => DoorEvent::Locked,
+        };
+        if event != DoorEvent::Locked {
+            self.mark_dirty(x, y);
+        }
+        Some(event)
+    }
+
+    /// Turns the locked door at (x, y) into a closed one. Returns false if
+    /// there is no locked door there.
+    pub fn unlock_door(&mut self, x: i32, y: i32) -> bool {
+        match self.doors.get_mut(&(x, y)) {
+            Some(door) if door.state == DoorState::Locked => {
+                door.state = DoorState::Closed;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Places closed doors in the one-cell gaps corridors punched through
+    /// the walls of `rooms`. Dead-end rooms other than the spawn room may
+    /// get their single door locked, with its key somewhere in a room that
+    /// isn't.
+    pub fn place_room_doors(&mut self, rooms: &[Rect], rng: &mut LevelRng) {
+        let spawn = (self.spawn[0] as i32, self.spawn[1] as i32);
+        let mut doorways = Vec::new();
+        for room in rooms {
+            let mut entrances = Vec::new();
+            for x in room.x1 + 1..room.x2 {
+                for y in [room.y1, room.y2] {
+                    if self.is_doorway(x, y, DoorAxis::Y) {
+                        entrances.push((x, y));
+                    }
+                }
+            }
+            for y in room.y1 + 1..room.y2 {
+                for x in [room.x1, room.x2] {
+                    if self.is_doorway(x, y, DoorAxis::X) {
+                        entrances.push((x, y));
+                    }
+                }
+            }
+
+            let has_spawn = (room.x1..=room.x2).contains(&spawn.0)
+                && (room.y1..=room.y2).contains(&spawn.1);
+            let lock = entrances.len() == 1 && !has_spawn && rng.gen_bool(LOCKED_DOOR_CHANCE);
+            doorways.push((entrances, lock));
+        }
+
+        let open_rooms: Vec<&Rect> = rooms
+            .iter()
+            .zip(&doorways)
+            .filter(|(_, (_, lock))| !lock)
+            .map(|(room, _)| room)
+            .collect();
+        for (entrances, lock) in doorways {
+            // without an open room there is nowhere to leave the key
+            let lock = lock && !open_rooms.is_empty();
+            for (x, y) in entrances {
+                if !lock {
+                    self.place_door(x, y, DoorState::Closed);
+                    continue;
+                }
+                self.place_door(x, y, DoorState::Locked);
+                let room = open_rooms[rng.gen_range(0..open_rooms.len() as u32) as usize];
+                let kx = rng.gen_range(room.x1 + 1..room.x2);
+                let ky = rng.gen_range(room.y1 + 1..room.y2);
+                self.add_trigger(Trigger::key(kx, ky, (x, y)));
+            }
+        }
+        log::info!("placed {} doors", self.doors.len());
+    }
+
+    /// A doorway is a floor cell in a wall: solid on both sides across the
+    /// passage and open on both ends of it.
+    fn is_doorway(&self, x: i32, y: i32, axis: DoorAxis) -> bool {
+        let (ends, sides) = match axis {
+            DoorAxis::X => ([(x - 1, y), (x + 1, y)], [(x, y - 1), (x, y + 1)]),
+            DoorAxis::Y => ([(x, y - 1), (x, y + 1)], [(x - 1, y), (x + 1, y)]),
+        };
+        self.get_value(x, y) == Some(TILE_FLOOR)
+            && ends.iter().all(|&(ex, ey)| self.get_value(ex, ey) == Some(TILE_FLOOR))
+            && sides.iter().all(|&(sx, sy)| self.get_value(sx, sy) == Some(TILE_WALL))
+    }
+}
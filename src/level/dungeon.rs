@@ -0,0 +1,211 @@
+use std::collections::{HashMap, VecDeque};
+use std::rc::Rc;
+
+use rand::{RngCore, SeedableRng};
+
+use super::connectivity::ConnectivityPolicy;
+use super::door::DoorState;
+use super::generator::GeneratorRegistry;
+use super::grid::Grid;
+use super::level::{Level, LevelRng, TILE_FLOOR, TILE_STAIRS_DOWN, TILE_STAIRS_UP};
+use super::tiles::TileRegistry;
+
+/// Which way a flight of stairs goes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Stairs {
+    Up,
+    Down,
+}
+
+/// Picks the generator used for a dungeon depth: rooms near the surface,
+/// then BSP layouts broken up by a cave every third floor.
+pub fn generator_for_depth(depth: u32) -> &'static str {
+    match depth {
+        0..=1 => "rooms",
+        d if d % 3 == 2 => "cave",
+        _ => "bsp",
+    }
+}
+
+/// A stack of levels joined by stairs. Floors are generated the first time
+/// they are visited and kept afterwards, so doors stay open and explored
+/// cells stay explored.
+pub struct Dungeon {
+    seed: u64,
+    size: [u32; 2],
+    tiles: Rc<TileRegistry>,
+    generators: GeneratorRegistry,
+    /// Use this generator on every floor instead of `generator_for_depth`.
+    generator: Option<String>,
+    policy: ConnectivityPolicy,
+    floors: HashMap<u32, Level>,
+    depth: u32,
+}
+
+impl Dungeon {
+    pub fn new(
+        seed: u64,
+        size: [u32; 2],
+        tiles: Rc<TileRegistry>,
+        generators: GeneratorRegistry,
+        generator: Option<String>,
+        policy: ConnectivityPolicy,
+        depth: u32,
+    ) -> Self {
+        let mut dungeon = Self {
+            seed,
+            size,
+            tiles,
+            generators,
+            generator,
+            policy,
+            floors: HashMap::new(),
+            depth,
+        };
+        dungeon.enter(depth);
+        dungeon
+    }
+
+    pub fn depth(&self) -> u32 {
+        self.depth
+    }
+
+    pub fn level(&self) -> &Level {
+        &self.floors[&self.depth]
+    }
+
+    pub fn level_mut(&mut self) -> &mut Level {
+        self.floors.get_mut(&self.depth).unwrap()
+    }
+
+    /// Replaces the current floor, for example with a hand-authored map.
+    pub fn set_level(&mut self, level: Level) {
+        self.floors.insert(self.depth, level);
+    }
+
+    /// RNG for one of a floor's streams. Every floor gets its own pair of
+    /// ChaCha streams (layout, then decoration) from the dungeon seed, so
+    /// floors can be generated in any order and still come out the same.
+    fn floor_rng(&self, depth: u32, stream: u64) -> LevelRng {
+        let mut rng = LevelRng::seed_from_u64(self.seed);
+        rng.set_stream(depth as u64 * 2 + stream);
+        rng
+    }
+
+    /// Seed for decorating the current floor. Each cell draws from its own
+    /// stream of it, so any part of the floor can be re-meshed alone.
+    pub fn decoration_seed(&self) -> u64 {
+        self.floor_rng(self.depth, 1).next_u64()
+    }
+
+    /// Makes `depth` the current floor, generating it if needed.
+    fn enter(&mut self, depth: u32) {
+        self.depth = depth;
+        if !self.floors.contains_key(&depth) {
+            let level = self.generate_floor(depth);
+            self.floors.insert(depth, level);
+        }
+    }
+
+    fn generate_floor(&self, depth: u32) -> Level {
+        let default = generator_for_depth(depth);
+        let name = self.generator.as_deref().unwrap_or(default);
+        let generator = self.generators.get(name).unwrap_or_else(|| {
+            log::warn!(
+                "unknown generator {}, expected one of {:?}; using {}",
+                name,
+                self.generators.names(),
+                default
+            );
+            self.generators.get(default).unwrap()
+        });
+
+        let mut level = Level::new(self.size[0], self.size[1], self.tiles.clone());
+        let mut rng = self.floor_rng(depth, 0);
+        let report = level.generate_connected(generator, &mut rng, self.policy);
+        level.place_stairs(depth > 0);
+        log::info!(
+            "depth {}: {} level with {} rooms, {} reachable cells, {} unreachable, after {} attempts{}",
+            depth,
+            level.metadata.generator,
+            level.metadata.rooms.len(),
+            report.reachable_cells,
+            report.unreachable_cells(),
+            report.attempts,
+            if report.repaired { " and repairs" } else { "" }
+        );
+        level
+    }
+
+    /// Takes the stairs to the floor above or below. Returns the cell the
+    /// player arrives on: the matching stairs on the new floor.
+    pub fn take_stairs(&mut self, stairs: Stairs) -> Option<[u32; 2]> {
+        let (depth, arrive_on) = match stairs {
+            Stairs::Down => (self.depth + 1, TILE_STAIRS_UP),
+            Stairs::Up if self.depth > 0 => (self.depth - 1, TILE_STAIRS_DOWN),
+            Stairs::Up => return None,
+        };
+        log::info!(
+            "leaving depth {} having explored {} cells",
+            self.depth,
+            self.level().explored_cells()
+        );
+        self.enter(depth);
+        let level = self.level();
+        let (x, y) = level
+            .find_tile(arrive_on)
+            .unwrap_or((level.spawn[0] as i32, level.spawn[1] as i32));
+        Some([x as u32, y as u32])
+    }
+}
+
+impl Level {
+    /// Returns the stairs at (x, y), if any.
+    pub fn stairs_at(&self, x: i32, y: i32) -> Option<Stairs> {
+        match self.get_value(x, y) {
+            Some(TILE_STAIRS_DOWN) => Some(Stairs::Down),
+            Some(TILE_STAIRS_UP) => Some(Stairs::Up),
+            _ => None,
+        }
+    }
+
+    /// Puts stairs down on the floor cell farthest from the spawn, and
+    /// stairs up on the spawn itself when `up` is set. Locked doors are
+    /// avoided so the way down is never shut.
+    pub fn place_stairs(&mut self, up: bool) {
+        let spawn = (self.spawn[0] as i32, self.spawn[1] as i32);
+        if !self.is_passable(spawn.0, spawn.1) {
+            log::warn!("spawn {:?} is not on floor, no stairs placed", spawn);
+            return;
+        }
+
+        let mut distance: Grid<Option<u32>> = Grid::new(self.width(), self.height(), None);
+        let mut queue = VecDeque::new();
+        *distance.get_mut(spawn.0, spawn.1).unwrap() = Some(0);
+        queue.push_back(spawn);
+        let mut farthest = (spawn, 0);
+        while let Some((x, y)) = queue.pop_front() {
+            let d = distance.get(x, y).unwrap().unwrap();
+            if d > farthest.1 && self.get_value(x, y) == Some(TILE_FLOOR) {
+                farthest = ((x, y), d);
+            }
+            for (nx, ny) in [(x + 1, y), (x - 1, y), (x, y + 1), (x, y - 1)] {
+                let locked = self.door(nx, ny).map(|d| d.state) == Some(DoorState::Locked);
+                if !self.is_traversable(nx, ny) || locked || distance.get(nx, ny) != Some(&None)
+                {
+                    continue;
+                }
+                *distance.get_mut(nx, ny).unwrap() = Some(d + 1);
+                queue.push_back((nx, ny));
+            }
+        }
+
+        let ((x, y), d) = farthest;
+        if d > 0 {
+            self.set_value(x as u32, y as u32, TILE_STAIRS_DOWN);
+        }
+        if up {
+            self.set_value(spawn.0 as u32, spawn.1 as u32, TILE_STAIRS_UP);
+        }
+    }
+}
@@ -0,0 +1,80 @@
+use super::bsp::BspGenerator;
+use super::cave::CaveGenerator;
+use super::level::{Level, LevelRng};
+use super::rooms::RoomsGenerator;
+
+/// An algorithm that fills a `Level` with tiles.
+///
+/// Generators draw all their randomness from the `rng` they are handed, so
+/// the same seed always produces the same level.
+pub trait LevelGenerator {
+    /// Name the generator is picked by, e.g. on the command line.
+    fn name(&self) -> &str;
+
+    /// Fills in the tiles, spawn point and metadata of `level`.
+    fn generate(&self, level: &mut Level, rng: &mut LevelRng);
+}
+
+/// The set of generators that can be picked by name.
+pub struct GeneratorRegistry {
+    generators: Vec<Box<dyn LevelGenerator>>,
+}
+
+impl GeneratorRegistry {
+    /// Creates a registry holding the built-in generators.
+    pub fn with_builtins() -> Self {
+        let mut registry = Self {
+            generators: Vec::new(),
+        };
+        registry.register(Box::new(RoomsGenerator::default()));
+        registry.register(Box::new(BspGenerator::default()));
+        registry.register(Box::new(CaveGenerator::default()));
+        registry
+    }
+
+    /// Adds a generator, replacing any existing one with the same name.
+    pub fn register(&mut self, generator: Box<dyn LevelGenerator>) {
+        self.generators.retain(|g| g.name() != generator.name());
+        self.generators.push(generator);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&dyn LevelGenerator> {
+        self.generators
+            .iter()
+            .find(|g| g.name() == name)
+            .map(|g| g.as_ref())
+    }
+
+    /// Names of every registered generator, in registration order.
+    pub fn names(&self) -> Vec<&str> {
+        self.generators.iter().map(|g| g.name()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::level::tiles::test_registry;
+
+    fn generate(generator: &dyn LevelGenerator, seed: u64) -> Level {
+        let mut level = Level::new(64, 48, test_registry());
+        level.generate(generator, &mut LevelRng::seed_from_u64(seed));
+        level
+    }
+
+    #[test]
+    fn same_seed_makes_the_same_level() {
+        let registry = GeneratorRegistry::with_builtins();
+        for name in registry.names() {
+            let generator = registry.get(name).unwrap();
+            let (a, b) = (generate(generator, 11), generate(generator, 11));
+            assert_eq!(a.to_ascii().unwrap(), b.to_ascii().unwrap(), "{}", name);
+            assert_eq!(a.spawn, b.spawn, "{}", name);
+
+            let other = generate(generator, 12);
+            assert_ne!(a.to_ascii().unwrap(), other.to_ascii().unwrap(), "{}", name);
+        }
+    }
+}
@@ -0,0 +1,58 @@
+/// A 2D grid of cells stored row-major in a single heap allocation.
+/// All accessors are bounds-checked and take signed coordinates, so callers
+/// can probe neighbours at -1 without wrapping.
+#[derive(Clone)]
+pub struct Grid<T> {
+    width: u32,
+    height: u32,
+    cells: Vec<T>,
+}
+
+impl<T: Clone> Grid<T> {
+    /// Creates a `width` x `height` grid with every cell set to `val`.
+    pub fn new(width: u32, height: u32, val: T) -> Self {
+        Self {
+            width,
+            height,
+            cells: vec![val; (width * height) as usize],
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Returns true if (x, y) lies inside the grid.
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        x >= 0 && y >= 0 && (x as u32) < self.width && (y as u32) < self.height
+    }
+
+    fn index(&self, x: i32, y: i32) -> Option<usize> {
+        if self.contains(x, y) {
+            Some(y as usize * self.width as usize + x as usize)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the cell at (x, y), or `None` if it is out of bounds.
+    pub fn get(&self, x: i32, y: i32) -> Option<&T> {
+        self.index(x, y).map(|i| &self.cells[i])
+    }
+
+    /// Returns the cell at (x, y) mutably, or `None` if it is out of bounds.
+    pub fn get_mut(&mut self, x: i32, y: i32) -> Option<&mut T> {
+        self.index(x, y).map(move |i| &mut self.cells[i])
+    }
+
+    /// Sets every cell to `val`.
+    pub fn fill(&mut self, val: T) {
+        for cell in self.cells.iter_mut() {
+            *cell = val.clone();
+        }
+    }
+}
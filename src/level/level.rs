@@ -1,26 +1,44 @@
-use rand::rngs::ThreadRng;
+use std::collections::HashMap;
+use std::rc::Rc;
+
 use rand::Rng;
+use rand_chacha::ChaCha8Rng;
 
-const LEVEL_WIDTH: i32 = 64;
-const LEVEL_HEIGHT: i32 = 64;
+use super::door::{Door, DoorState};
+use super::generator::LevelGenerator;
+use super::grid::Grid;
+use super::tiles::{Tile, TileRegistry};
+use super::trigger::Trigger;
 
-/// Wall tile
-const TILE_WALL: u32 = 40;
+/// RNG used for level generation. ChaCha8 produces the same stream for a
+/// given seed on every platform, so a seed always rebuilds the same level.
+pub type LevelRng = ChaCha8Rng;
+
+// Ids of the core tiles. Every tile manifest defines these and the
+// `TileRegistry` always hands them out first, so they are fixed.
 /// Floor tile
-const TILE_FLOOR: u32 = 0;
+pub const TILE_FLOOR: u32 = 0;
+/// Wall tile
+pub const TILE_WALL: u32 = 1;
+/// Door tile, drawn as a slab; its state lives in `Level::doors`
+pub const TILE_DOOR: u32 = 2;
+/// Stairs leading down to the next floor
+pub const TILE_STAIRS_DOWN: u32 = 3;
+/// Stairs leading up to the previous floor
+pub const TILE_STAIRS_UP: u32 = 4;
 
 /// Simple rectangle struct for convenience
-#[derive(Copy, Clone)]
-struct Rect {
-    x1: i32,
-    y1: i32,
-    x2: i32,
-    y2: i32,
+#[derive(Debug, Copy, Clone)]
+pub struct Rect {
+    pub x1: i32,
+    pub y1: i32,
+    pub x2: i32,
+    pub y2: i32,
 }
 
 impl Rect {
     /// Creates a new Rect from an (x, y) origin, width (w), and height (h).
-    fn new(x: i32, y: i32, w: i32, h: i32) -> Self {
+    pub fn new(x: i32, y: i32, w: i32, h: i32) -> Self {
         Self {
             x1: x,
             y1: y,
@@ -30,7 +48,7 @@ impl Rect {
     }
 
     /// Returns true if this rectangle intersects another.
-    fn intersects(&self, other: &Rect) -> bool {
+    pub fn intersects(&self, other: &Rect) -> bool {
         !(self.x2 < other.x1
             || self.x1 > other.x2
             || self.y2 < other.y1
@@ -38,44 +56,192 @@ impl Rect {
     }
 
     /// Returns the center of this rectangle as (x, y).
-    fn center(&self) -> (i32, i32) {
+    pub fn center(&self) -> (i32, i32) {
         let center_x = (self.x1 + self.x2) / 2;
         let center_y = (self.y1 + self.y2) / 2;
         (center_x, center_y)
     }
 }
 
+/// Information a generator records about the level it built.
+#[derive(Clone, Default)]
+pub struct LevelMetadata {
+    /// Name of the generator that built the level.
+    pub generator: String,
+    /// Rooms carved by the generator, if it works in rooms.
+    pub rooms: Vec<Rect>,
+}
+
 pub struct Level {
-    rng: ThreadRng,
-    pub data: [[u32; LEVEL_WIDTH as usize]; LEVEL_HEIGHT as usize],
+    /// Tile ids, looked up in `tiles`.
+    data: Grid<u32>,
+    tiles: Rc<TileRegistry>,
+    pub(super) doors: HashMap<(i32, i32), Door>,
+    /// Cells the player has seen.
+    pub(super) explored: Grid<bool>,
+    pub(super) triggers: Vec<Trigger>,
+    /// Box around the cells changed since the last `take_dirty`.
+    dirty: Option<Rect>,
     pub spawn: [u32; 2],
+    pub metadata: LevelMetadata,
 }
 
 impl Level {
-    pub fn new() -> Self {
+    pub fn new(width: u32, height: u32, tiles: Rc<TileRegistry>) -> Self {
         Self {
-            rng: rand::thread_rng(),
-            data: [[0; LEVEL_WIDTH as usize]; LEVEL_HEIGHT as usize],
+            data: Grid::new(width, height, TILE_FLOOR),
+            tiles,
+            doors: HashMap::new(),
+            explored: Grid::new(width, height, false),
+            triggers: Vec::new(),
+            dirty: None,
             spawn: [1, 1],
+            metadata: LevelMetadata::default(),
         }
     }
 
-    /// Sets the tile at (x, y) to `val`.
+    /// Clears the level's doors, triggers and metadata and lets `generator`
+    /// fill it in.
+    pub fn generate(&mut self, generator: &dyn LevelGenerator, rng: &mut LevelRng) {
+        self.doors.clear();
+        self.triggers.clear();
+        self.explored.fill(false);
+        self.metadata = LevelMetadata {
+            generator: generator.name().to_string(),
+            ..LevelMetadata::default()
+        };
+        generator.generate(self, rng);
+    }
+
+    pub fn width(&self) -> u32 {
+        self.data.width()
+    }
+
+    pub fn height(&self) -> u32 {
+        self.data.height()
+    }
+
+    /// Sets the tile at (x, y) to `val`. Writes outside the level are ignored.
+    /// Overwriting a door removes it; use `place_door` to add one.
     pub fn set_value(&mut self, x: u32, y: u32, val: u32) {
-        self.data[y as usize][x as usize] = val;
+        match self.data.get_mut(x as i32, y as i32) {
+            Some(cell) => *cell = val,
+            None => {
+                log::warn!("set_value out of bounds: {}, {}", x, y);
+                return;
+            }
+        }
+        if val != TILE_DOOR {
+            self.doors.remove(&(x as i32, y as i32));
+        }
+        self.mark_dirty(x as i32, y as i32);
     }
 
-    /// A helper to fill the map with a certain tile.
-    fn fill(&mut self, val: u32) {
-        for y in 0..LEVEL_HEIGHT {
-            for x in 0..LEVEL_WIDTH {
-                self.set_value(x as u32, y as u32, val);
+    /// Records that the cell at (x, y) looks different, so its geometry
+    /// needs rebuilding.
+    pub fn mark_dirty(&mut self, x: i32, y: i32) {
+        self.dirty = Some(match self.dirty {
+            Some(rect) => Rect {
+                x1: rect.x1.min(x),
+                y1: rect.y1.min(y),
+                x2: rect.x2.max(x),
+                y2: rect.y2.max(y),
+            },
+            None => Rect::new(x, y, 1, 1),
+        });
+    }
+
+    /// Returns the box around every cell changed since the last call, and
+    /// starts tracking afresh.
+    pub fn take_dirty(&mut self) -> Option<Rect> {
+        self.dirty.take()
+    }
+
+    /// Returns the tile at (x, y), or `None` if (x, y) lies outside the level.
+    pub fn get_value(&self, x: i32, y: i32) -> Option<u32> {
+        self.data.get(x, y).copied()
+    }
+
+    pub fn tiles(&self) -> &Rc<TileRegistry> {
+        &self.tiles
+    }
+
+    /// Returns the registry entry for the tile at (x, y).
+    pub fn tile(&self, x: i32, y: i32) -> Option<&Tile> {
+        self.tiles.get(self.get_value(x, y)?)
+    }
+
+    /// Returns true if the player can move into the cell at (x, y) right now.
+    /// Cells outside the level are never passable.
+    pub fn is_passable(&self, x: i32, y: i32) -> bool {
+        match self.get_value(x, y) {
+            Some(TILE_DOOR) => self.door(x, y).map(|d| d.state) == Some(DoorState::Open),
+            Some(_) => self.tile(x, y).is_some_and(|t| t.walkable),
+            None => false,
+        }
+    }
+
+    /// Returns true if (x, y) is floor or a door in any state, i.e. part of
+    /// the walkable layout once doors are opened.
+    pub fn is_traversable(&self, x: i32, y: i32) -> bool {
+        self.get_value(x, y) == Some(TILE_DOOR) || self.is_passable(x, y)
+    }
+
+    /// Returns the first cell holding `tile`, scanning row by row.
+    pub fn find_tile(&self, tile: u32) -> Option<(i32, i32)> {
+        (0..self.height() as i32)
+            .flat_map(|y| (0..self.width() as i32).map(move |x| (x, y)))
+            .find(|&(x, y)| self.get_value(x, y) == Some(tile))
+    }
+
+    /// Splits the traversable cells into 4-connected regions, largest first.
+    pub fn floor_regions(&self) -> Vec<Vec<(i32, i32)>> {
+        self.regions(|_, _| true)
+    }
+
+    /// Splits the traversable cells for which `include` holds into
+    /// 4-connected regions, largest first.
+    pub(super) fn regions(&self, include: impl Fn(i32, i32) -> bool) -> Vec<Vec<(i32, i32)>> {
+        let open = |x, y| self.is_traversable(x, y) && include(x, y);
+        let mut seen = Grid::new(self.width(), self.height(), false);
+        let mut regions = Vec::new();
+        for y in 0..self.height() as i32 {
+            for x in 0..self.width() as i32 {
+                if seen.get(x, y) == Some(&true) || !open(x, y) {
+                    continue;
+                }
+                let mut region = Vec::new();
+                let mut stack = vec![(x, y)];
+                *seen.get_mut(x, y).unwrap() = true;
+                while let Some((cx, cy)) = stack.pop() {
+                    region.push((cx, cy));
+                    for (nx, ny) in [(cx + 1, cy), (cx - 1, cy), (cx, cy + 1), (cx, cy - 1)] {
+                        if !open(nx, ny) {
+                            continue;
+                        }
+                        if let Some(visited) = seen.get_mut(nx, ny) {
+                            if !*visited {
+                                *visited = true;
+                                stack.push((nx, ny));
+                            }
+                        }
+                    }
+                }
+                regions.push(region);
             }
         }
+        regions.sort_by_key(|r| std::cmp::Reverse(r.len()));
+        regions
+    }
+
+    /// A helper to fill the map with a certain tile.
+    pub fn fill(&mut self, val: u32) {
+        self.data.fill(val);
+        self.dirty = Some(Rect::new(0, 0, self.width() as i32, self.height() as i32));
     }
 
     /// Build a corridor in a horizontal line.
-    fn carve_horizontal_corridor(&mut self, x1: i32, x2: i32, y: i32) {
+    pub fn carve_horizontal_corridor(&mut self, x1: i32, x2: i32, y: i32) {
         let min_x = x1.min(x2);
         let max_x = x1.max(x2);
         for x in min_x..=max_x {
@@ -84,7 +250,7 @@ impl Level {
     }
 
     /// Build a corridor in a vertical line.
-    fn carve_vertical_corridor(&mut self, y1: i32, y2: i32, x: i32) {
+    pub fn carve_vertical_corridor(&mut self, y1: i32, y2: i32, x: i32) {
         let min_y = y1.min(y2);
         let max_y = y1.max(y2);
         for y in min_y..=max_y {
@@ -93,7 +259,7 @@ impl Level {
     }
 
     /// Carve out a rectangular room: walls around the perimeter, floors inside.
-    fn carve_room(&mut self, rect: &Rect) {
+    pub fn carve_room(&mut self, rect: &Rect) {
         for y in rect.y1..=rect.y2 {
             for x in rect.x1..=rect.x2 {
                 // If on the border of the room, keep a wall
@@ -107,63 +273,19 @@ impl Level {
         }
     }
 
-    pub fn build(&mut self) {
-        // 1) Fill entire map with walls
-        self.fill(TILE_WALL);
-
-        // 2) Repeatedly attempt to place random rooms
-        let max_rooms = 10;       // up to 10 rooms
-        let min_size = 5;         // room min dimension
-        let max_size = 12;        // room max dimension
-        let mut rooms = Vec::new();
-
-        for _ in 0..50 { // Up to 50 attempts to place rooms
-            let w = self.rng.gen_range(min_size..=max_size);
-            let h = self.rng.gen_range(min_size..=max_size);
-            let x = self.rng.gen_range(1..(LEVEL_WIDTH - w - 1));
-            let y = self.rng.gen_range(1..(LEVEL_HEIGHT - h - 1));
-            let new_room = Rect::new(x, y, w, h);
-
-            // Check for intersection with existing rooms
-            let mut failed = false;
-            for other_room in &rooms {
-                if new_room.intersects(other_room) {
-                    failed = true;
-                    break;
-                }
-            }
-            if !failed {
-                // Carve out this new room
-                self.carve_room(&new_room);
-                rooms.push(new_room);
-                if rooms.len() >= max_rooms {
-                    break;
-                }
-            }
-        }
-
-        // 3) Connect rooms with corridors
-        // We'll link each room to the previous one with an L‐shaped corridor
-        for i in 1..rooms.len() {
-            let (prev_x, prev_y) = rooms[i - 1].center();
-            let (new_x, new_y)   = rooms[i].center();
-
-            // Randomly decide if we carve horizontally first or vertically first
-            if self.rng.gen_bool(0.5) {
-                // carve horizontally, then vertically
-                self.carve_horizontal_corridor(prev_x, new_x, prev_y);
-                self.carve_vertical_corridor(prev_y, new_y, new_x);
-            } else {
-                // carve vertically, then horizontally
-                self.carve_vertical_corridor(prev_y, new_y, prev_x);
-                self.carve_horizontal_corridor(prev_x, new_x, new_y);
-            }
-        }
-
-        // 4) Place spawn in the first room if any exist
-        if !rooms.is_empty() {
-            let (spawn_x, spawn_y) = rooms[0].center();
-            self.spawn = [spawn_x as u32, spawn_y as u32];
+    /// Join two points with an L-shaped corridor, randomly choosing whether
+    /// the horizontal or the vertical leg comes first.
+    pub fn carve_l_corridor(&mut self, from: (i32, i32), to: (i32, i32), rng: &mut LevelRng) {
+        let (prev_x, prev_y) = from;
+        let (new_x, new_y) = to;
+        if rng.gen_bool(0.5) {
+            // carve horizontally, then vertically
+            self.carve_horizontal_corridor(prev_x, new_x, prev_y);
+            self.carve_vertical_corridor(prev_y, new_y, new_x);
+        } else {
+            // carve vertically, then horizontally
+            self.carve_vertical_corridor(prev_y, new_y, prev_x);
+            self.carve_horizontal_corridor(prev_x, new_x, new_y);
         }
     }
 }
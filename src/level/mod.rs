@@ -1 +1,15 @@
-pub mod level;
\ No newline at end of file
+pub mod ascii;
+pub mod bsp;
+pub mod cave;
+pub mod connectivity;
+pub mod corridors;
+pub mod door;
+pub mod dungeon;
+pub mod generator;
+pub mod grid;
+pub mod level;
+pub mod rooms;
+pub mod sight;
+pub mod tiled;
+pub mod tiles;
+pub mod trigger;
\ No newline at end of file
@@ -0,0 +1,88 @@
+use rand::Rng;
+
+use super::generator::LevelGenerator;
+use super::level::{Level, LevelRng, Rect, TILE_WALL};
+
+/// Places random non-overlapping rooms and links them with a minimum
+/// spanning tree of corridors, plus the odd loop.
+#[derive(Clone)]
+pub struct RoomsGenerator {
+    /// Stop once this many rooms have been placed.
+    pub max_rooms: usize,
+    /// Give up after this many placement attempts.
+    pub max_attempts: u32,
+    /// Smallest room dimension, walls included.
+    pub min_size: i32,
+    /// Largest room dimension, walls included.
+    pub max_size: i32,
+    /// Chance for each room to get an extra corridor to its nearest
+    /// neighbour, creating loops and alternate routes.
+    pub loop_chance: f64,
+}
+
+impl Default for RoomsGenerator {
+    fn default() -> Self {
+        Self {
+            max_rooms: 10,
+            max_attempts: 50,
+            min_size: 5,
+            max_size: 12,
+            loop_chance: 0.2,
+        }
+    }
+}
+
+impl LevelGenerator for RoomsGenerator {
+    fn name(&self) -> &str {
+        "rooms"
+    }
+
+    fn generate(&self, level: &mut Level, rng: &mut LevelRng) {
+        // 1) Fill entire map with walls
+        level.fill(TILE_WALL);
+
+        // 2) Repeatedly attempt to place random rooms
+        let min_size = self.min_size;
+        let mut rooms: Vec<Rect> = Vec::new();
+
+        // Rooms need a one-cell margin on each side of the map
+        let width = level.width() as i32;
+        let height = level.height() as i32;
+        let max_size = self.max_size.min(width - 3).min(height - 3);
+        if max_size < min_size {
+            log::warn!("level {}x{} is too small for rooms", width, height);
+            return;
+        }
+
+        for _ in 0..self.max_attempts {
+            let w = rng.gen_range(min_size..=max_size);
+            let h = rng.gen_range(min_size..=max_size);
+            let x = rng.gen_range(1..(width - w - 1));
+            let y = rng.gen_range(1..(height - h - 1));
+            let new_room = Rect::new(x, y, w, h);
+
+            // Check for intersection with existing rooms
+            if !rooms.iter().any(|other_room| new_room.intersects(other_room)) {
+                // Carve out this new room
+                level.carve_room(&new_room);
+                rooms.push(new_room);
+                if rooms.len() >= self.max_rooms {
+                    break;
+                }
+            }
+        }
+
+        // 3) Connect rooms with corridors along a minimum spanning tree
+        level.connect_rooms(&rooms, self.loop_chance, rng);
+
+        // 4) Place spawn in the first room if any exist
+        if let Some(room) = rooms.first() {
+            let (spawn_x, spawn_y) = room.center();
+            level.spawn = [spawn_x as u32, spawn_y as u32];
+        }
+
+        // 5) Hang doors where corridors enter rooms
+        level.place_room_doors(&rooms, rng);
+        level.metadata.rooms = rooms;
+    }
+}
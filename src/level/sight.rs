@@ -0,0 +1,131 @@
+use super::door::DoorState;
+use super::level::{Level, TILE_DOOR};
+
+impl Level {
+    /// Returns true if nothing can be seen through (x, y): walls, closed or
+    /// locked doors, and anything outside the level.
+    pub fn blocks_sight(&self, x: i32, y: i32) -> bool {
+        match self.get_value(x, y) {
+            Some(TILE_DOOR) => self.door(x, y).map(|d| d.state) != Some(DoorState::Open),
+            Some(_) => match self.tile(x, y) {
+                Some(tile) => tile.blocks_sight,
+                None => true,
+            },
+            None => true,
+        }
+    }
+
+    /// Returns true if there is an unblocked line from (x0, y0) to (x1, y1).
+    /// The end cells themselves may block sight, so walls are visible.
+    pub fn has_line_of_sight(&self, x0: i32, y0: i32, x1: i32, y1: i32) -> bool {
+        // Bresenham, checking every cell strictly between the two ends
+        let dx = (x1 - x0).abs();
+        let dy = -(y1 - y0).abs();
+        let sx = if x0 < x1 { 1 } else { -1 };
+        let sy = if y0 < y1 { 1 } else { -1 };
+        let mut err = dx + dy;
+        let (mut x, mut y) = (x0, y0);
+        loop {
+            if (x, y) == (x1, y1) {
+                return true;
+            }
+            if (x, y) != (x0, y0) && self.blocks_sight(x, y) {
+                return false;
+            }
+            let e2 = 2 * err;
+            if e2 >= dy {
+                err += dy;
+                x += sx;
+            }
+            if e2 <= dx {
+                err += dx;
+                y += sy;
+            }
+        }
+    }
+
+    /// Marks every cell within `radius` of (x, y) that is in line of sight
+    /// as explored.
+    pub fn reveal(&mut self, x: i32, y: i32, radius: i32) {
+        for cy in y - radius..=y + radius {
+            for cx in x - radius..=x + radius {
+                if !self.explored.contains(cx, cy)
+                    || (cx - x).pow(2) + (cy - y).pow(2) > radius * radius
+                {
+                    continue;
+                }
+                if self.has_line_of_sight(x, y, cx, cy) {
+                    *self.explored.get_mut(cx, cy).unwrap() = true;
+                }
+            }
+        }
+    }
+
+    /// Number of cells the player has seen on this level.
+    pub fn explored_cells(&self) -> usize {
+        (0..self.height() as i32)
+            .flat_map(|y| (0..self.width() as i32).map(move |x| (x, y)))
+            .filter(|&(x, y)| self.explored.get(x, y) == Some(&true))
+            .count()
+    }
+
+    /// Draws the explored part of the level as text, one line per row:
+    /// `@` for the player at `player`, `#` for walls, `+` for doors, `.` for
+    /// anything walkable and blanks for cells not yet seen.
+    pub fn explored_map(&self, player: [i32; 2]) -> String {
+        let mut map = String::new();
+        for y in 0..self.height() as i32 {
+            for x in 0..self.width() as i32 {
+                map.push(if [x, y] == player {
+                    '@'
+                } else if self.explored.get(x, y) != Some(&true) {
+                    ' '
+                } else if self.door(x, y).is_some() {
+                    '+'
+                } else if self.is_passable(x, y) {
+                    '.'
+                } else {
+                    '#'
+                });
+            }
+            map.push('\n');
+        }
+        map
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::level::level::Level;
+    use crate::level::tiles::test_registry;
+
+    fn corridor(door: char) -> Level {
+        let map = format!("#######\n#@.{}..#\n#######\n", door);
+        Level::from_ascii(&map, test_registry()).unwrap()
+    }
+
+    #[test]
+    fn closed_and_locked_doors_block_sight() {
+        for door in ['+', '&'] {
+            let mut level = corridor(door);
+            assert!(level.blocks_sight(3, 1));
+            assert!(!level.is_passable(3, 1));
+            assert!(!level.has_line_of_sight(1, 1, 5, 1));
+            level.reveal(1, 1, 8);
+            // the door itself is seen, nothing past it
+            assert_eq!(level.explored.get(3, 1), Some(&true));
+            assert_eq!(level.explored.get(4, 1), Some(&false));
+        }
+    }
+
+    #[test]
+    fn open_doors_let_sight_through() {
+        let mut level = corridor('+');
+        level.use_door(3, 1);
+        assert!(!level.blocks_sight(3, 1));
+        assert!(level.is_passable(3, 1));
+        assert!(level.has_line_of_sight(1, 1, 5, 1));
+        level.reveal(1, 1, 8);
+        assert_eq!(level.explored.get(5, 1), Some(&true));
+    }
+}
@@ -0,0 +1,195 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use serde::Deserialize;
+use serde_json::Value;
+
+use super::{load_tileset, Object, TileLayer, TiledError, TiledMap, Tileset};
+
+#[derive(Deserialize)]
+struct JsonMap {
+    width: u32,
+    height: u32,
+    tilewidth: u32,
+    tileheight: u32,
+    #[serde(default)]
+    orientation: Option<String>,
+    #[serde(default)]
+    infinite: bool,
+    #[serde(default)]
+    tilesets: Vec<JsonTileset>,
+    #[serde(default)]
+    layers: Vec<JsonLayer>,
+}
+
+#[derive(Deserialize)]
+struct JsonTileset {
+    /// Missing in external tileset files, the map supplies it.
+    #[serde(default)]
+    firstgid: u32,
+    source: Option<String>,
+    #[serde(default)]
+    name: String,
+    image: Option<String>,
+    #[serde(default)]
+    tiles: Vec<JsonTile>,
+}
+
+#[derive(Deserialize)]
+struct JsonTile {
+    id: u32,
+    image: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct JsonLayer {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    name: String,
+    /// An array of gids, or a string when the data is encoded.
+    data: Option<Value>,
+    #[serde(default)]
+    encoding: Option<String>,
+    #[serde(default)]
+    objects: Vec<JsonObject>,
+    /// Children of a group layer.
+    #[serde(default)]
+    layers: Vec<JsonLayer>,
+}
+
+#[derive(Deserialize)]
+struct JsonObject {
+    #[serde(default)]
+    name: String,
+    #[serde(rename = "type", default)]
+    kind: String,
+    #[serde(default)]
+    class: String,
+    #[serde(default)]
+    x: f32,
+    #[serde(default)]
+    y: f32,
+    #[serde(default)]
+    width: f32,
+    #[serde(default)]
+    height: f32,
+    #[serde(default)]
+    properties: Vec<JsonProperty>,
+}
+
+#[derive(Deserialize)]
+struct JsonProperty {
+    name: String,
+    value: Value,
+}
+
+impl JsonTileset {
+    fn into_tileset(self, first_gid: u32) -> Tileset {
+        Tileset {
+            first_gid,
+            name: self.name,
+            image: self.image,
+            tiles: self
+                .tiles
+                .into_iter()
+                .filter_map(|tile| Some((tile.id, tile.image?)))
+                .collect(),
+        }
+    }
+}
+
+pub(super) fn parse_map(text: &str, dir: &Path) -> Result<TiledMap, TiledError> {
+    let json: JsonMap = serde_json::from_str(text)?;
+    let orientation = json.orientation.as_deref().unwrap_or("orthogonal");
+    if orientation != "orthogonal" {
+        return Err(TiledError::UnsupportedMap(orientation.to_string()));
+    }
+    if json.infinite {
+        return Err(TiledError::UnsupportedMap("infinite".to_string()));
+    }
+
+    let mut tilesets = Vec::new();
+    for tileset in json.tilesets {
+        let first_gid = tileset.firstgid;
+        let tileset = match &tileset.source {
+            Some(source) => load_tileset(&dir.join(source), first_gid)?,
+            None => tileset.into_tileset(first_gid),
+        };
+        tilesets.push(tileset);
+    }
+
+    let mut map = TiledMap {
+        width: json.width,
+        height: json.height,
+        tile_width: json.tilewidth,
+        tile_height: json.tileheight,
+        tilesets,
+        layers: Vec::new(),
+        objects: Vec::new(),
+    };
+    read_layers(json.layers, &mut map)?;
+    Ok(map)
+}
+
+pub(super) fn parse_tileset(text: &str, first_gid: u32) -> Result<Tileset, TiledError> {
+    let json: JsonTileset = serde_json::from_str(text)?;
+    Ok(json.into_tileset(first_gid))
+}
+
+/// Collects tile and object layers, descending into groups.
+fn read_layers(layers: Vec<JsonLayer>, map: &mut TiledMap) -> Result<(), TiledError> {
+    for layer in layers {
+        match layer.kind.as_str() {
+            "tilelayer" => {
+                let gids = match layer.data {
+                    Some(Value::String(_)) => {
+                        return Err(TiledError::UnsupportedEncoding {
+                            layer: layer.name,
+                            encoding: layer.encoding.unwrap_or_else(|| "encoded".to_string()),
+                        })
+                    }
+                    Some(data) => serde_json::from_value(data)?,
+                    None => Vec::new(),
+                };
+                map.layers.push(TileLayer {
+                    name: layer.name,
+                    gids,
+                });
+            }
+            "objectgroup" => map
+                .objects
+                .extend(layer.objects.into_iter().map(read_object)),
+            "group" => read_layers(layer.layers, map)?,
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+fn read_object(object: JsonObject) -> Object {
+    let properties: HashMap<String, String> = object
+        .properties
+        .into_iter()
+        .map(|p| {
+            let value = match p.value {
+                Value::String(s) => s,
+                other => other.to_string(),
+            };
+            (p.name, value)
+        })
+        .collect();
+    Object {
+        name: object.name,
+        kind: if object.kind.is_empty() {
+            object.class
+        } else {
+            object.kind
+        },
+        x: object.x,
+        y: object.y,
+        width: object.width,
+        height: object.height,
+        properties,
+    }
+}
@@ -0,0 +1,531 @@
+mod json;
+mod tmx;
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::rc::Rc;
+
+use thiserror::Error;
+
+use super::door::DoorState;
+use super::level::{Level, Rect, TILE_DOOR};
+use super::tiles::TileRegistry;
+use super::trigger::Trigger;
+
+/// Tiled keeps the flip and rotation flags in the top bits of a gid.
+const GID_MASK: u32 = 0x0fff_ffff;
+
+#[derive(Debug, Error)]
+pub enum TiledError {
+    #[error{"{0}"}]
+    IoError(#[from] std::io::Error),
+    #[error{"{0}"}]
+    XmlError(#[from] roxmltree::Error),
+    #[error{"{0}"}]
+    JsonError(#[from] serde_json::Error),
+    #[error("{0}: not a Tiled file, expected .tmx, .tmj, .tsx or .tsj")]
+    UnknownFormat(String),
+    #[error("<{element}> has a missing or invalid {attribute} attribute")]
+    BadAttribute { element: String, attribute: String },
+    #[error("{0} maps are not supported, only finite orthogonal ones")]
+    UnsupportedMap(String),
+    #[error("layer {layer}: {encoding} data is not supported, save the map with CSV layer data")]
+    UnsupportedEncoding { layer: String, encoding: String },
+    #[error("layer {layer} has {found} tiles, expected {expected}")]
+    BadLayer {
+        layer: String,
+        expected: usize,
+        found: usize,
+    },
+    #[error("tileset {tileset}: image {image} is not the texture of any tile in the registry")]
+    UnresolvedTile { tileset: String, image: String },
+    #[error("tileset {tileset} is a single image ({image}) that is not the loaded tilesheet, use an image collection of the tile images or load the sheet with --tiles")]
+    UnsupportedTileset { tileset: String, image: String },
+    #[error("gid {0} does not belong to any tileset, or shows no tile in the registry")]
+    UnknownGid(u32),
+    #[error("map has no spawn, add an object of type spawn")]
+    MissingSpawn,
+    #[error("spawn at {x}, {y} is outside the map or not on a walkable cell")]
+    SpawnOutOfBounds { x: i32, y: i32 },
+}
+
+/// A Tiled map, read from either TMX or JSON.
+struct TiledMap {
+    width: u32,
+    height: u32,
+    tile_width: u32,
+    tile_height: u32,
+    tilesets: Vec<Tileset>,
+    layers: Vec<TileLayer>,
+    objects: Vec<Object>,
+}
+
+struct Tileset {
+    first_gid: u32,
+    name: String,
+    /// Image of a tilesheet tileset.
+    image: Option<String>,
+    /// Per-tile images of an image collection tileset, by tile id.
+    tiles: Vec<(u32, String)>,
+}
+
+struct TileLayer {
+    name: String,
+    gids: Vec<u32>,
+}
+
+struct Object {
+    name: String,
+    /// The object's type, or its class in newer versions of Tiled.
+    kind: String,
+    x: f32,
+    y: f32,
+    width: f32,
+    height: f32,
+    properties: HashMap<String, String>,
+}
+
+/// Reads an external tileset, `.tsx` or `.tsj`, referenced from a map.
+fn load_tileset(path: &Path, first_gid: u32) -> Result<Tileset, TiledError> {
+    let text = fs::read_to_string(path)?;
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("tsx") => tmx::parse_tileset(&text, first_gid),
+        Some("tsj") | Some("json") => json::parse_tileset(&text, first_gid),
+        _ => Err(TiledError::UnknownFormat(path.display().to_string())),
+    }
+}
+
+/// The file name at the end of an image path.
+fn file_name(image: &str) -> &str {
+    Path::new(image)
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or_default()
+}
+
+/// Maps the gids of a single image tileset, which only works when the
+/// engine's textures were sliced from that same sheet: tile `n` of the sheet
+/// is then texture layer `<sheet>#n`. Sheet tiles no registry tile shows are
+/// left out.
+fn resolve_sheet(
+    tileset: &Tileset,
+    tile_layers: &HashMap<String, u32>,
+    tiles: &TileRegistry,
+    gids: &mut HashMap<u32, u32>,
+) -> Result<(), TiledError> {
+    let image = tileset.image.clone().unwrap_or_default();
+    let prefix = format!("{}#", file_name(&image));
+    let mut loaded = false;
+    for (name, &layer) in tile_layers {
+        let id: u32 = match name.strip_prefix(&prefix).and_then(|n| n.parse().ok()) {
+            Some(id) => id,
+            None => continue,
+        };
+        loaded = true;
+        if let Some(tile) = tiles.by_texture(layer) {
+            gids.insert(tileset.first_gid + id, tile);
+        }
+    }
+    if !loaded {
+        return Err(TiledError::UnsupportedTileset {
+            tileset: tileset.name.clone(),
+            image,
+        });
+    }
+    Ok(())
+}
+
+impl TiledMap {
+    /// Maps every gid the tilesets define to the registry tile with the same
+    /// texture.
+    fn resolve_gids(
+        &self,
+        tile_layers: &HashMap<String, u32>,
+        tiles: &TileRegistry,
+    ) -> Result<HashMap<u32, u32>, TiledError> {
+        let mut gids = HashMap::new();
+        for tileset in &self.tilesets {
+            if tileset.tiles.is_empty() {
+                resolve_sheet(tileset, tile_layers, tiles, &mut gids)?;
+                continue;
+            }
+            for (id, image) in &tileset.tiles {
+                let tile = tile_layers
+                    .get(file_name(image))
+                    .and_then(|&layer| tiles.by_texture(layer));
+                let tile = match tile {
+                    Some(tile) => tile,
+                    None => {
+                        return Err(TiledError::UnresolvedTile {
+                            tileset: tileset.name.clone(),
+                            image: image.clone(),
+                        })
+                    }
+                };
+                gids.insert(tileset.first_gid + id, tile);
+            }
+        }
+        Ok(gids)
+    }
+
+    /// The cell under a point given in map pixels.
+    fn cell(&self, x: f32, y: f32) -> (i32, i32) {
+        (
+            (x / self.tile_width as f32).floor() as i32,
+            (y / self.tile_height as f32).floor() as i32,
+        )
+    }
+
+    fn into_level(
+        self,
+        tile_layers: &HashMap<String, u32>,
+        tiles: Rc<TileRegistry>,
+    ) -> Result<Level, TiledError> {
+        let gids = self.resolve_gids(tile_layers, &tiles)?;
+        let mut level = Level::new(self.width, self.height, tiles);
+        level.metadata.generator = "tiled".to_string();
+
+        // tile layers stack, later layers painting over earlier ones; cells
+        // no layer paints stay floor
+        let expected = (self.width * self.height) as usize;
+        for layer in &self.layers {
+            if layer.gids.len() != expected {
+                return Err(TiledError::BadLayer {
+                    layer: layer.name.clone(),
+                    expected,
+                    found: layer.gids.len(),
+                });
+            }
+            for (i, gid) in layer.gids.iter().enumerate() {
+                let gid = gid & GID_MASK;
+                if gid == 0 {
+                    continue;
+                }
+                let tile = *gids.get(&gid).ok_or(TiledError::UnknownGid(gid))?;
+                level.set_value(i as u32 % self.width, i as u32 / self.width, tile);
+            }
+        }
+        // painted door tiles start closed, door objects below can override
+        for y in 0..self.height as i32 {
+            for x in 0..self.width as i32 {
+                if level.get_value(x, y) == Some(TILE_DOOR) {
+                    level.place_door(x, y, DoorState::Closed);
+                }
+            }
+        }
+
+        let mut spawn = None;
+        for object in &self.objects {
+            // points have no size, so the center works for them as well
+            let (x, y) = self.cell(
+                object.x + object.width / 2.0,
+                object.y + object.height / 2.0,
+            );
+            match object.kind.as_str() {
+                "spawn" => match spawn {
+                    Some(first) => log::warn!(
+                        "ignoring spawn at {}, {}, already spawning at {:?}",
+                        x,
+                        y,
+                        first
+                    ),
+                    None => spawn = Some((x, y)),
+                },
+                "door" => {
+                    let state = match object.properties.get("state").map(|s| s.as_str()) {
+                        None | Some("closed") => DoorState::Closed,
+                        Some("open") => DoorState::Open,
+                        Some("locked") => DoorState::Locked,
+                        Some(other) => {
+                            log::warn!("door at {}, {}: unknown state {}, closing it", x, y, other);
+                            DoorState::Closed
+                        }
+                    };
+                    if level.get_value(x, y).is_some() {
+                        level.place_door(x, y, state);
+                    } else {
+                        log::warn!("ignoring door outside the map at {}, {}", x, y);
+                    }
+                }
+                "trigger" => {
+                    let (x1, y1) = self.cell(object.x, object.y);
+                    let (x2, y2) = self.cell(object.x + object.width, object.y + object.height);
+                    // the far edge of the rectangle belongs to the next cell
+                    let area = Rect {
+                        x1,
+                        y1,
+                        x2: (x2 - 1).max(x1),
+                        y2: (y2 - 1).max(y1),
+                    };
+                    level.add_trigger(Trigger {
+                        name: object.name.clone(),
+                        area,
+                        properties: object.properties.clone(),
+                    });
+                }
+                kind => log::warn!("ignoring object {} of unknown type '{}'", object.name, kind),
+            }
+        }
+        let (x, y) = spawn.ok_or(TiledError::MissingSpawn)?;
+        // also rules out negative cells, which would wrap as u32
+        if !level.is_passable(x, y) {
+            return Err(TiledError::SpawnOutOfBounds { x, y });
+        }
+        level.spawn = [x as u32, y as u32];
+        Ok(level)
+    }
+}
+
+impl Level {
+    /// Imports a map made in the Tiled editor, either TMX or JSON. Tile
+    /// layers become the level's tiles and objects of type `spawn`, `door`
+    /// and `trigger` are placed on it.
+    ///
+    /// Tilesets must be image collections of the tile images, or the very
+    /// tilesheet the textures were sliced from. Each tile's image file name,
+    /// or `<sheet>#<tile id>`, is looked up in `tile_layers` to find its
+    /// texture array layer, and the map cell gets the registry tile showing
+    /// it.
+    pub fn load_tiled(
+        path: &Path,
+        tile_layers: &HashMap<String, u32>,
+        tiles: Rc<TileRegistry>,
+    ) -> Result<Level, TiledError> {
+        let text = fs::read_to_string(path)?;
+        let dir = path.parent().unwrap_or_else(|| Path::new(""));
+        let map = match path.extension().and_then(|e| e.to_str()) {
+            Some("tmx") => tmx::parse_map(&text, dir)?,
+            Some("tmj") | Some("json") => json::parse_map(&text, dir)?,
+            _ => return Err(TiledError::UnknownFormat(path.display().to_string())),
+        };
+        map.into_level(tile_layers, tiles)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::level::level::{TILE_FLOOR, TILE_WALL};
+    use crate::level::tiles::{test_layers, test_registry};
+
+    /// A 4x3 map walled round the edge, with the given objects.
+    fn walled_map(objects: Vec<Object>) -> TiledMap {
+        #[rustfmt::skip]
+        let gids = vec![
+            1, 1, 1, 1,
+            1, 0, 0, 1,
+            1, 1, 1, 1,
+        ];
+        TiledMap {
+            width: 4,
+            height: 3,
+            tile_width: 16,
+            tile_height: 16,
+            tilesets: vec![Tileset {
+                first_gid: 1,
+                name: "walls".to_string(),
+                image: None,
+                tiles: vec![(0, "tile_0040.png".to_string())],
+            }],
+            layers: vec![TileLayer {
+                name: "walls".to_string(),
+                gids,
+            }],
+            objects,
+        }
+    }
+
+    fn spawn_at(x: f32, y: f32) -> Object {
+        Object {
+            name: "start".to_string(),
+            kind: "spawn".to_string(),
+            x,
+            y,
+            width: 0.0,
+            height: 0.0,
+            properties: HashMap::new(),
+        }
+    }
+
+    fn load(map: TiledMap) -> Result<Level, TiledError> {
+        map.into_level(&test_layers(), test_registry())
+    }
+
+    #[test]
+    fn spawn_on_floor_is_kept() {
+        let level = load(walled_map(vec![spawn_at(40.0, 24.0)])).unwrap();
+        assert_eq!(level.spawn, [2, 1]);
+    }
+
+    #[test]
+    fn spawn_off_the_floor_is_rejected() {
+        // on a wall, left of the map and below it
+        let spawns = [(8.0, 8.0, (0, 0)), (-8.0, 24.0, (-1, 1)), (24.0, 56.0, (1, 3))];
+        for &(x, y, cell) in &spawns {
+            match load(walled_map(vec![spawn_at(x, y)])) {
+                Err(TiledError::SpawnOutOfBounds { x, y }) => assert_eq!((x, y), cell),
+                other => panic!("spawn at {:?}: got {:?}", cell, other.err()),
+            }
+        }
+    }
+
+    /// A tileset of the wall and door images, gids 1 and 2.
+    const TSX_TILES: &str = r#"<tileset firstgid="1" name="tiles" tilewidth="16" tileheight="16">
+  <tile id="0"><image width="16" height="16" source="../tiles/tile_0040.png"/></tile>
+  <tile id="1"><image width="16" height="16" source="../tiles/tile_0045.png"/></tile>
+ </tileset>"#;
+
+    /// Objects shared by the fixtures: a spawn at (1, 1) and a locked door
+    /// at (2, 1).
+    const TMX_OBJECTS: &str = r#"<objectgroup name="objects">
+  <object id="1" name="start" type="spawn" x="16" y="16"/>
+  <object id="2" name="door" class="door" x="32" y="16" width="16" height="16">
+   <properties><property name="state" value="locked"/></properties>
+  </object>
+ </objectgroup>"#;
+
+    /// A 4x3 TMX map of `tileset` and `layers`.
+    fn tmx(tileset: &str, layers: &str) -> String {
+        format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.10" orientation="orthogonal" infinite="0"
+     width="4" height="3" tilewidth="16" tileheight="16">
+ {}
+ {}
+ {}
+</map>"#,
+            tileset, layers, TMX_OBJECTS
+        )
+    }
+
+    fn import_tmx(text: &str, layers: &HashMap<String, u32>) -> Result<Level, TiledError> {
+        tmx::parse_map(text, Path::new(""))?.into_level(layers, test_registry())
+    }
+
+    /// Walls round the edge with a door at (2, 1), horizontally flipped.
+    fn assert_walled_room(level: &Level) {
+        assert_eq!((level.width(), level.height()), (4, 3));
+        assert_eq!(level.spawn, [1, 1]);
+        for x in 0..4 {
+            assert_eq!(level.get_value(x, 0), Some(TILE_WALL));
+            assert_eq!(level.get_value(x, 2), Some(TILE_WALL));
+        }
+        assert_eq!(level.get_value(1, 1), Some(TILE_FLOOR));
+        assert_eq!(level.get_value(2, 1), Some(TILE_DOOR));
+        assert_eq!(level.door(2, 1).map(|d| d.state), Some(DoorState::Locked));
+    }
+
+    #[test]
+    fn reads_tmx_csv_data_in_groups_with_flip_flags() {
+        let layers = r#"<group name="building">
+  <layer id="1" name="walls" width="4" height="3">
+   <data encoding="csv">
+1,1,1,1,
+1,0,2147483650,1,
+1,1,1,1
+</data>
+  </layer>
+ </group>"#;
+        assert_walled_room(&import_tmx(&tmx(TSX_TILES, layers), &test_layers()).unwrap());
+    }
+
+    #[test]
+    fn reads_tmx_tile_elements() {
+        let mut data = String::new();
+        for gid in [1u32, 1, 1, 1, 1, 0, 2147483650, 1, 1, 1, 1, 1] {
+            match gid {
+                0 => data.push_str("<tile/>"),
+                gid => data.push_str(&format!(r#"<tile gid="{}"/>"#, gid)),
+            }
+        }
+        let layers = format!(r#"<layer name="walls"><data>{}</data></layer>"#, data);
+        assert_walled_room(&import_tmx(&tmx(TSX_TILES, &layers), &test_layers()).unwrap());
+
+        let encoded = r#"<layer name="walls"><data encoding="base64">AQAAAA==</data></layer>"#;
+        assert!(matches!(
+            import_tmx(&tmx(TSX_TILES, encoded), &test_layers()),
+            Err(TiledError::UnsupportedEncoding { encoding, .. }) if encoding == "base64"
+        ));
+    }
+
+    #[test]
+    fn reads_json_maps() {
+        let text = r#"{
+            "width": 4, "height": 3, "tilewidth": 16, "tileheight": 16,
+            "orientation": "orthogonal", "infinite": false,
+            "tilesets": [{
+                "firstgid": 1, "name": "tiles",
+                "tiles": [
+                    { "id": 0, "image": "../tiles/tile_0040.png" },
+                    { "id": 1, "image": "../tiles/tile_0045.png" }
+                ]
+            }],
+            "layers": [
+                { "type": "group", "name": "building", "layers": [{
+                    "type": "tilelayer", "name": "walls",
+                    "data": [1, 1, 1, 1, 1, 0, 2147483650, 1, 1, 1, 1, 1]
+                }]},
+                { "type": "objectgroup", "name": "objects", "objects": [
+                    { "name": "start", "type": "spawn", "x": 16, "y": 16 },
+                    { "name": "door", "class": "door", "x": 32, "y": 16, "width": 16,
+                      "height": 16, "properties": [{ "name": "state", "value": "locked" }] },
+                    { "name": "alarm", "type": "trigger", "x": 16, "y": 16, "width": 32,
+                      "height": 16, "properties": [{ "name": "volume", "value": 3 }] }
+                ]}
+            ]
+        }"#;
+        let map = json::parse_map(text, Path::new("")).unwrap();
+        let level = map.into_level(&test_layers(), test_registry()).unwrap();
+        assert_walled_room(&level);
+        let trigger = level.triggers_at(2, 1).next().unwrap();
+        assert_eq!(trigger.name, "alarm");
+        assert_eq!((trigger.area.x1, trigger.area.x2), (1, 2));
+        assert_eq!(trigger.properties["volume"], "3");
+    }
+
+    #[test]
+    fn unknown_tilesets_fail_cleanly() {
+        let layers = r#"<layer name="walls">
+  <data encoding="csv">1,1,1,1,1,0,0,1,1,1,1,1</data>
+ </layer>"#;
+        let unknown = r#"<tileset firstgid="1" name="props">
+  <tile id="0"><image source="props/barrel.png"/></tile>
+ </tileset>"#;
+        match import_tmx(&tmx(unknown, layers), &test_layers()) {
+            Err(TiledError::UnresolvedTile { tileset, image }) => {
+                assert_eq!((tileset.as_str(), image.as_str()), ("props", "props/barrel.png"))
+            }
+            other => panic!("got {:?}", other.err()),
+        }
+
+        let sheet = r#"<tileset firstgid="1" name="sheet" tilewidth="16" tileheight="16">
+  <image source="sheet.png" width="192" height="128"/>
+ </tileset>"#;
+        assert!(matches!(
+            import_tmx(&tmx(sheet, layers), &test_layers()),
+            Err(TiledError::UnsupportedTileset { image, .. }) if image == "sheet.png"
+        ));
+    }
+
+    #[test]
+    fn tilesheets_resolve_against_their_own_slices() {
+        // the textures as `--tiles sheet.png` would load them, one layer per
+        // tile of the sheet
+        let layers: HashMap<String, u32> = (0..test_layers().len() as u32)
+            .map(|layer| (format!("sheet.png#{}", layer), layer))
+            .collect();
+        let wall = 1 + test_layers()["tile_0040.png"];
+        let door = 1 + test_layers()["tile_0045.png"];
+        let sheet = r#"<tileset firstgid="1" name="sheet" tilewidth="16" tileheight="16">
+  <image source="../sheet.png" width="192" height="128"/>
+ </tileset>"#;
+        let data = format!(
+            "{w},{w},{w},{w},{w},0,{d},{w},{w},{w},{w},{w}",
+            w = wall,
+            d = door | 0x8000_0000
+        );
+        let walls = format!(r#"<layer name="walls"><data encoding="csv">{}</data></layer>"#, data);
+        assert_walled_room(&import_tmx(&tmx(sheet, &walls), &layers).unwrap());
+    }
+}
@@ -0,0 +1,170 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::str::FromStr;
+
+use roxmltree::{Document, Node};
+
+use super::{load_tileset, Object, TileLayer, TiledError, TiledMap, Tileset};
+
+/// Reads a numeric attribute, failing if it is missing or malformed.
+fn attribute<T: FromStr>(node: Node, name: &str) -> Result<T, TiledError> {
+    node.attribute(name)
+        .and_then(|value| value.parse().ok())
+        .ok_or_else(|| TiledError::BadAttribute {
+            element: node.tag_name().name().to_string(),
+            attribute: name.to_string(),
+        })
+}
+
+/// Reads an optional numeric attribute, falling back to `default`.
+fn attribute_or<T: FromStr>(node: Node, name: &str, default: T) -> Result<T, TiledError> {
+    match node.attribute(name) {
+        Some(_) => attribute(node, name),
+        None => Ok(default),
+    }
+}
+
+fn children<'a, 'input: 'a>(
+    node: Node<'a, 'input>,
+    name: &'a str,
+) -> impl Iterator<Item = Node<'a, 'input>> + 'a {
+    node.children().filter(move |n| n.has_tag_name(name))
+}
+
+pub(super) fn parse_map(text: &str, dir: &Path) -> Result<TiledMap, TiledError> {
+    let document = Document::parse(text)?;
+    let root = document.root_element();
+    let orientation = root.attribute("orientation").unwrap_or("orthogonal");
+    if orientation != "orthogonal" {
+        return Err(TiledError::UnsupportedMap(orientation.to_string()));
+    }
+    if root.attribute("infinite") == Some("1") {
+        return Err(TiledError::UnsupportedMap("infinite".to_string()));
+    }
+
+    let mut tilesets = Vec::new();
+    for node in children(root, "tileset") {
+        let first_gid = attribute(node, "firstgid")?;
+        let tileset = match node.attribute("source") {
+            Some(source) => load_tileset(&dir.join(source), first_gid)?,
+            None => read_tileset(node, first_gid)?,
+        };
+        tilesets.push(tileset);
+    }
+
+    let mut map = TiledMap {
+        width: attribute(root, "width")?,
+        height: attribute(root, "height")?,
+        tile_width: attribute(root, "tilewidth")?,
+        tile_height: attribute(root, "tileheight")?,
+        tilesets,
+        layers: Vec::new(),
+        objects: Vec::new(),
+    };
+    read_layers(root, &mut map)?;
+    Ok(map)
+}
+
+pub(super) fn parse_tileset(text: &str, first_gid: u32) -> Result<Tileset, TiledError> {
+    let document = Document::parse(text)?;
+    read_tileset(document.root_element(), first_gid)
+}
+
+fn read_tileset(node: Node, first_gid: u32) -> Result<Tileset, TiledError> {
+    let image_source = |n: Node| {
+        children(n, "image")
+            .next()
+            .and_then(|image| image.attribute("source"))
+            .map(|source| source.to_string())
+    };
+    let mut tiles = Vec::new();
+    for tile in children(node, "tile") {
+        if let Some(image) = image_source(tile) {
+            tiles.push((attribute(tile, "id")?, image));
+        }
+    }
+    Ok(Tileset {
+        first_gid,
+        name: node.attribute("name").unwrap_or_default().to_string(),
+        image: image_source(node),
+        tiles,
+    })
+}
+
+/// Collects tile and object layers, descending into groups.
+fn read_layers(parent: Node, map: &mut TiledMap) -> Result<(), TiledError> {
+    for node in parent.children() {
+        match node.tag_name().name() {
+            "layer" => map.layers.push(read_tile_layer(node)?),
+            "objectgroup" => {
+                for object in children(node, "object") {
+                    map.objects.push(read_object(object)?);
+                }
+            }
+            "group" => read_layers(node, map)?,
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+fn read_tile_layer(node: Node) -> Result<TileLayer, TiledError> {
+    let name = node.attribute("name").unwrap_or_default().to_string();
+    let data = match children(node, "data").next() {
+        Some(data) => data,
+        None => {
+            return Ok(TileLayer {
+                name,
+                gids: Vec::new(),
+            })
+        }
+    };
+    let gids = match data.attribute("encoding") {
+        Some("csv") => data
+            .text()
+            .unwrap_or_default()
+            .split(',')
+            .map(|gid| gid.trim().parse().ok())
+            .collect::<Option<Vec<u32>>>()
+            .ok_or_else(|| TiledError::BadAttribute {
+                element: "data".to_string(),
+                attribute: "csv".to_string(),
+            })?,
+        None => children(data, "tile")
+            .map(|tile| attribute_or(tile, "gid", 0))
+            .collect::<Result<Vec<u32>, _>>()?,
+        Some(encoding) => {
+            return Err(TiledError::UnsupportedEncoding {
+                layer: name,
+                encoding: encoding.to_string(),
+            })
+        }
+    };
+    Ok(TileLayer { name, gids })
+}
+
+fn read_object(node: Node) -> Result<Object, TiledError> {
+    let mut properties = HashMap::new();
+    for list in children(node, "properties") {
+        for property in children(list, "property") {
+            // long values are written as the element's text instead
+            let value = property.attribute("value").or_else(|| property.text());
+            if let (Some(name), Some(value)) = (property.attribute("name"), value) {
+                properties.insert(name.to_string(), value.to_string());
+            }
+        }
+    }
+    Ok(Object {
+        name: node.attribute("name").unwrap_or_default().to_string(),
+        kind: node
+            .attribute("type")
+            .or_else(|| node.attribute("class"))
+            .unwrap_or_default()
+            .to_string(),
+        x: attribute_or(node, "x", 0.0)?,
+        y: attribute_or(node, "y", 0.0)?,
+        width: attribute_or(node, "width", 0.0)?,
+        height: attribute_or(node, "height", 0.0)?,
+        properties,
+    })
+}
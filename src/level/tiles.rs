@@ -0,0 +1,426 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use rand::Rng;
+use serde::Deserialize;
+use thiserror::Error;
+
+use super::level::LevelRng;
+
+/// Tiles every manifest has to define. They always get these ids, in this
+/// order, which is what the `TILE_*` constants in `level` refer to.
+const CORE_TILES: [&str; 5] = ["floor", "wall", "door", "stairs_down", "stairs_up"];
+
+#[derive(Debug, Error)]
+pub enum TileRegistryError {
+    #[error{"{0}"}]
+    IoError(#[from] std::io::Error),
+    #[error{"{0}"}]
+    JsonError(#[from] serde_json::Error),
+    #[error("tile {0} is defined more than once")]
+    DuplicateTile(String),
+    #[error("no {0} tile, the manifest must define floor, wall, door, stairs_down and stairs_up")]
+    MissingCoreTile(String),
+    #[error("tile {tile}: unknown texture {texture}")]
+    UnknownTexture { tile: String, texture: String },
+    #[error("tile {tile} needs a {face} texture")]
+    MissingFace { tile: String, face: &'static str },
+    #[error("tile {tile}: the {face} face needs at least one texture with a positive weight")]
+    EmptyFace { tile: String, face: &'static str },
+    #[error("tile {0}: animations need at least one frame and a positive frame_time")]
+    BadAnimation(String),
+}
+
+#[derive(Deserialize)]
+struct Manifest {
+    tiles: Vec<TileDef>,
+}
+
+#[derive(Deserialize)]
+struct TileDef {
+    name: String,
+    #[serde(default)]
+    walkable: bool,
+    /// Defaults to the opposite of `walkable`.
+    blocks_sight: Option<bool>,
+    side: Option<FaceDef>,
+    top: Option<FaceDef>,
+    bottom: Option<FaceDef>,
+    animation: Option<AnimationDef>,
+    light: Option<Light>,
+}
+
+/// A face is one texture, or weighted variants picked at random per cell.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum FaceDef {
+    Texture(String),
+    Variants(Vec<VariantDef>),
+}
+
+#[derive(Deserialize)]
+struct VariantDef {
+    texture: String,
+    #[serde(default = "default_weight")]
+    weight: u32,
+}
+
+fn default_weight() -> u32 {
+    1
+}
+
+#[derive(Deserialize)]
+struct AnimationDef {
+    frames: Vec<String>,
+    frame_time: f64,
+}
+
+/// Light given off by a tile.
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct Light {
+    pub color: [f32; 3],
+    /// Distance in cells at which the light has faded out.
+    pub radius: f32,
+}
+
+/// The texture layers one face of a tile can show.
+#[derive(Debug, Clone)]
+pub struct Face {
+    /// (layer, weight) pairs.
+    variants: Vec<(u32, u32)>,
+}
+
+impl Face {
+    /// Picks one of the face's textures. Faces with a single texture leave
+    /// `rng` alone.
+    pub fn pick(&self, rng: &mut LevelRng) -> u32 {
+        if self.variants.len() == 1 {
+            return self.variants[0].0;
+        }
+        let total: u32 = self.variants.iter().map(|&(_, weight)| weight).sum();
+        let mut n = rng.gen_range(0..total.max(1));
+        for &(layer, weight) in &self.variants {
+            if n < weight {
+                return layer;
+            }
+            n -= weight;
+        }
+        self.variants[self.variants.len() - 1].0
+    }
+
+    fn contains(&self, layer: u32) -> bool {
+        self.variants.iter().any(|&(l, _)| l == layer)
+    }
+}
+
+/// Texture frames a tile's sides cycle through.
+#[derive(Debug, Clone)]
+pub struct Animation {
+    pub frames: Vec<u32>,
+    /// Seconds each frame is shown for.
+    pub frame_time: f64,
+}
+
+impl Animation {
+    /// Index of the frame showing `time` seconds in.
+    pub fn frame(&self, time: f64) -> usize {
+        (time / self.frame_time) as usize % self.frames.len()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Tile {
+    pub name: String,
+    /// The player can walk onto the tile. Solid tiles are drawn as blocks.
+    pub walkable: bool,
+    pub blocks_sight: bool,
+    side: Face,
+    top: Face,
+    bottom: Face,
+    pub animation: Option<Animation>,
+    pub light: Option<Light>,
+}
+
+impl Tile {
+    /// Texture for the sides of the tile `time` seconds in.
+    pub fn side(&self, rng: &mut LevelRng, time: f64) -> u32 {
+        match &self.animation {
+            Some(animation) => animation.frames[animation.frame(time)],
+            None => self.side.pick(rng),
+        }
+    }
+
+    /// Texture for the top of a block, or the ceiling above a walkable tile.
+    pub fn top(&self, rng: &mut LevelRng) -> u32 {
+        self.top.pick(rng)
+    }
+
+    /// Texture for the bottom of a block, or the floor under a walkable tile.
+    pub fn bottom(&self, rng: &mut LevelRng) -> u32 {
+        self.bottom.pick(rng)
+    }
+
+    fn uses_texture(&self, layer: u32) -> bool {
+        self.side.contains(layer)
+            || self.top.contains(layer)
+            || self.bottom.contains(layer)
+            || self
+                .animation
+                .as_ref()
+                .is_some_and(|a| a.frames.contains(&layer))
+    }
+}
+
+/// Every tile a level can hold, loaded from a manifest. A level stores tile
+/// ids, which index into the registry.
+pub struct TileRegistry {
+    tiles: Vec<Tile>,
+    ids: HashMap<String, u32>,
+}
+
+impl TileRegistry {
+    /// Loads a tile manifest. Textures are image file names, looked up in
+    /// `tile_layers` to find their texture array layer.
+    pub fn load(
+        path: &Path,
+        tile_layers: &HashMap<String, u32>,
+    ) -> Result<TileRegistry, TileRegistryError> {
+        let registry = Self::parse(&fs::read_to_string(path)?, tile_layers)?;
+        log::info!("loaded {} tiles from {}", registry.tiles.len(), path.display());
+        Ok(registry)
+    }
+
+    /// Reads a tile manifest from its text, see `load`.
+    pub(crate) fn parse(
+        text: &str,
+        tile_layers: &HashMap<String, u32>,
+    ) -> Result<TileRegistry, TileRegistryError> {
+        let manifest: Manifest = serde_json::from_str(text)?;
+
+        let mut defs: Vec<TileDef> = Vec::new();
+        for def in manifest.tiles {
+            if defs.iter().any(|d| d.name == def.name) {
+                return Err(TileRegistryError::DuplicateTile(def.name));
+            }
+            defs.push(def);
+        }
+        // core tiles first, in the order their ids are fixed in
+        for (id, name) in CORE_TILES.iter().enumerate() {
+            let at = defs
+                .iter()
+                .position(|d| d.name == *name)
+                .ok_or_else(|| TileRegistryError::MissingCoreTile(name.to_string()))?;
+            let def = defs.remove(at);
+            defs.insert(id, def);
+        }
+
+        let tiles = defs
+            .into_iter()
+            .map(|def| resolve_tile(def, tile_layers))
+            .collect::<Result<Vec<_>, _>>()?;
+        let ids = tiles
+            .iter()
+            .enumerate()
+            .map(|(id, tile)| (tile.name.clone(), id as u32))
+            .collect();
+        Ok(TileRegistry { tiles, ids })
+    }
+
+    pub fn get(&self, id: u32) -> Option<&Tile> {
+        self.tiles.get(id as usize)
+    }
+
+    /// Returns the id of the tile called `name`.
+    pub fn id(&self, name: &str) -> Option<u32> {
+        self.ids.get(name).copied()
+    }
+
+    /// Returns the tile showing texture `layer`, preferring one that shows
+    /// it on its sides.
+    pub fn by_texture(&self, layer: u32) -> Option<u32> {
+        let side = self.tiles.iter().position(|t| t.side.contains(layer));
+        side.or_else(|| self.tiles.iter().position(|t| t.uses_texture(layer)))
+            .map(|id| id as u32)
+    }
+
+    /// The current frame of every animated tile `time` seconds in. When this
+    /// changes, animated geometry needs rebuilding.
+    pub fn animation_frames(&self, time: f64) -> Vec<usize> {
+        self.tiles
+            .iter()
+            .filter_map(|t| t.animation.as_ref())
+            .map(|a| a.frame(time))
+            .collect()
+    }
+}
+
+fn resolve_tile(
+    def: TileDef,
+    tile_layers: &HashMap<String, u32>,
+) -> Result<Tile, TileRegistryError> {
+    let name = def.name;
+    let layer = |texture: &str| {
+        tile_layers
+            .get(texture)
+            .copied()
+            .ok_or_else(|| TileRegistryError::UnknownTexture {
+                tile: name.clone(),
+                texture: texture.to_string(),
+            })
+    };
+    let face = |def: Option<FaceDef>, face| -> Result<Option<Face>, TileRegistryError> {
+        let variants = match def {
+            None => return Ok(None),
+            Some(FaceDef::Texture(texture)) => vec![(layer(&texture)?, 1)],
+            Some(FaceDef::Variants(variants)) => variants
+                .iter()
+                .map(|v| Ok((layer(&v.texture)?, v.weight)))
+                .collect::<Result<Vec<_>, TileRegistryError>>()?,
+        };
+        // `Face::pick` needs something to pick
+        if variants.iter().all(|&(_, weight)| weight == 0) {
+            return Err(TileRegistryError::EmptyFace {
+                tile: name.clone(),
+                face,
+            });
+        }
+        Ok(Some(Face { variants }))
+    };
+
+    let animation = match def.animation {
+        Some(animation) => {
+            if animation.frames.is_empty() || animation.frame_time <= 0.0 {
+                return Err(TileRegistryError::BadAnimation(name));
+            }
+            Some(Animation {
+                frames: animation
+                    .frames
+                    .iter()
+                    .map(|frame| layer(frame))
+                    .collect::<Result<_, _>>()?,
+                frame_time: animation.frame_time,
+            })
+        }
+        None => None,
+    };
+    let missing = |face| TileRegistryError::MissingFace {
+        tile: name.clone(),
+        face,
+    };
+    let side = face(def.side, "side")?.or_else(|| {
+        animation.as_ref().map(|a| Face {
+            variants: vec![(a.frames[0], 1)],
+        })
+    });
+    let top = face(def.top, "top")?;
+    let bottom = face(def.bottom, "bottom")?;
+
+    // walkable tiles need a floor and ceiling, solid ones their sides;
+    // whatever else is left out falls back to those
+    let (side, top, bottom) = if def.walkable {
+        let top = top.ok_or_else(|| missing("top"))?;
+        let bottom = bottom.ok_or_else(|| missing("bottom"))?;
+        (side.unwrap_or_else(|| bottom.clone()), top, bottom)
+    } else {
+        let side = side.ok_or_else(|| missing("side"))?;
+        (
+            side.clone(),
+            top.unwrap_or_else(|| side.clone()),
+            bottom.unwrap_or(side),
+        )
+    };
+
+    Ok(Tile {
+        blocks_sight: def.blocks_sight.unwrap_or(!def.walkable),
+        walkable: def.walkable,
+        side,
+        top,
+        bottom,
+        animation,
+        light: def.light,
+        name,
+    })
+}
+
+/// Texture layers of the game's tile images, numbered by the same
+/// `tile_files` order `TextureArray::load` uploads them in.
+#[cfg(test)]
+pub fn test_layers() -> HashMap<String, u32> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("assets/tiles");
+    crate::renderer::texture::tile_files(&dir)
+        .unwrap()
+        .iter()
+        .enumerate()
+        .map(|(layer, file)| {
+            let name = file.file_name().unwrap().to_str().unwrap().to_string();
+            (name, layer as u32)
+        })
+        .collect()
+}
+
+/// The game's own tile manifest, loaded against `test_layers`.
+#[cfg(test)]
+pub fn test_registry() -> std::rc::Rc<TileRegistry> {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("assets/tiles.json");
+    std::rc::Rc::new(TileRegistry::load(&path, &test_layers()).unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+
+    use super::*;
+
+    /// A manifest of the core tiles with `wall` as given.
+    fn manifest(wall: &str) -> String {
+        format!(
+            r#"{{"tiles": [
+                {{ "name": "floor", "walkable": true, "top": "tile_0000.png",
+                   "bottom": "tile_0048.png" }},
+                {wall},
+                {{ "name": "door", "side": "tile_0045.png" }},
+                {{ "name": "stairs_down", "side": "tile_0036.png" }},
+                {{ "name": "stairs_up", "side": "tile_0039.png" }}
+            ]}}"#,
+            wall = wall
+        )
+    }
+
+    #[test]
+    fn faces_with_nothing_to_pick_are_rejected() {
+        let walls = [
+            (r#"{ "name": "wall", "side": [] }"#, "side"),
+            (r#"{ "name": "wall", "side": "tile_0040.png", "top": [] }"#, "top"),
+            (
+                r#"{ "name": "wall", "side": "tile_0040.png", "bottom": [
+                    { "texture": "tile_0040.png", "weight": 0 },
+                    { "texture": "tile_0041.png", "weight": 0 }
+                ] }"#,
+                "bottom",
+            ),
+        ];
+        for (wall, expected) in walls {
+            match TileRegistry::parse(&manifest(wall), &test_layers()) {
+                Err(TileRegistryError::EmptyFace { tile, face }) => {
+                    assert_eq!((tile.as_str(), face), ("wall", expected))
+                }
+                other => panic!("{}: got {:?}", wall, other.err()),
+            }
+        }
+    }
+
+    #[test]
+    fn zero_weights_are_never_picked() {
+        let wall = r#"{ "name": "wall", "side": [
+            { "texture": "tile_0040.png", "weight": 0 },
+            { "texture": "tile_0041.png" }
+        ] }"#;
+        let tiles = TileRegistry::parse(&manifest(wall), &test_layers()).unwrap();
+        let wall = tiles.get(tiles.id("wall").unwrap()).unwrap();
+        let mut rng = LevelRng::seed_from_u64(0);
+        for _ in 0..20 {
+            assert_eq!(wall.side(&mut rng, 0.0), test_layers()["tile_0041.png"]);
+        }
+    }
+}
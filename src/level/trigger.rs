@@ -0,0 +1,63 @@
+use std::collections::HashMap;
+
+use super::level::{Level, Rect};
+
+/// Trigger property naming the door a trigger is the key to, as `"x,y"`.
+pub const UNLOCKS: &str = "unlocks";
+
+/// A named area that fires when the player steps into it. Triggers come
+/// from map files; what they do is up to their properties. One with an
+/// `unlocks` property is a key, unlocking that door when stepped on.
+#[derive(Debug, Clone)]
+pub struct Trigger {
+    pub name: String,
+    /// Cells covered by the trigger, inclusive.
+    pub area: Rect,
+    pub properties: HashMap<String, String>,
+}
+
+impl Trigger {
+    /// A key on the cell (x, y) to the locked door at `door`.
+    pub fn key(x: i32, y: i32, door: (i32, i32)) -> Self {
+        let mut properties = HashMap::new();
+        properties.insert(UNLOCKS.to_string(), format!("{},{}", door.0, door.1));
+        Self {
+            name: "key".to_string(),
+            area: Rect::new(x, y, 1, 1),
+            properties,
+        }
+    }
+
+    /// The door this trigger unlocks, if it is a key.
+    pub fn unlocks(&self) -> Option<(i32, i32)> {
+        let (x, y) = self.properties.get(UNLOCKS)?.split_once(',')?;
+        Some((x.trim().parse().ok()?, y.trim().parse().ok()?))
+    }
+
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        (self.area.x1..=self.area.x2).contains(&x) && (self.area.y1..=self.area.y2).contains(&y)
+    }
+}
+
+impl Level {
+    pub fn add_trigger(&mut self, trigger: Trigger) {
+        self.triggers.push(trigger);
+    }
+
+    /// Returns the triggers covering (x, y).
+    pub fn triggers_at(&self, x: i32, y: i32) -> impl Iterator<Item = &Trigger> {
+        self.triggers.iter().filter(move |t| t.contains(x, y))
+    }
+
+    /// Returns the keys to the door at (x, y).
+    pub fn keys_to(&self, x: i32, y: i32) -> impl Iterator<Item = &Trigger> {
+        self.triggers.iter().filter(move |t| t.unlocks() == Some((x, y)))
+    }
+
+    /// Unlocks the doors whose keys are at (x, y). Returns the doors that
+    /// were unlocked.
+    pub fn unlock_from(&mut self, x: i32, y: i32) -> Vec<(i32, i32)> {
+        let doors: Vec<(i32, i32)> = self.triggers_at(x, y).filter_map(Trigger::unlocks).collect();
+        doors.into_iter().filter(|&(dx, dy)| self.unlock_door(dx, dy)).collect()
+    }
+}
@@ -1,36 +1,61 @@
+mod assets;
+mod input;
 mod level;
 mod models;
 mod renderer;
 mod ui;
 
+use std::collections::HashMap;
+use std::env;
 use std::ffi::CStr;
+use std::fmt::Display;
 use std::os::raw::c_void;
 use std::path::Path;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::rc::Rc;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
 
+use crate::assets::AssetError;
+use crate::input::{Action, Bindings, Button};
+use crate::level::connectivity::ConnectivityPolicy;
+use crate::level::door::DoorEvent;
+use crate::level::dungeon::Dungeon;
+use crate::level::generator::GeneratorRegistry;
 use crate::level::level::Level;
-use crate::models::cube::Cube;
-use crate::renderer::camera::Camera;
-use crate::renderer::texture::{TextureArray};
-use crate::renderer::Renderer;
+use crate::level::tiles::TileRegistry;
+use crate::models::level_mesh::{
+    animated_chunks, build_level_mesh, rebuild_chunks, rebuild_level_mesh, MeshOptions,
+};
+use crate::renderer::camera::{
+    Camera, CameraMode, Move, TurnMode, WalkEvent, DEFAULT_TRANSITION_TIME,
+};
+use crate::renderer::texture::TextureArray;
+use crate::renderer::{PointLight, Projection, Renderer};
 use crate::ui::ui::UserInterface;
 use gl::types::{GLchar, GLenum, GLsizei, GLuint};
 use glam::Vec3;
 use glutin::dpi::{LogicalPosition, LogicalSize};
-use glutin::event::{ElementState, Event, KeyboardInput, WindowEvent};
+use glutin::event::{DeviceEvent, ElementState, Event, KeyboardInput, ModifiersState, WindowEvent};
 use glutin::event_loop::{ControlFlow, EventLoop};
 use glutin::monitor::MonitorHandle;
-use glutin::window::WindowBuilder;
+use glutin::window::{CursorGrabMode, Window, WindowBuilder};
 use glutin::{Api, ContextBuilder, GlRequest};
 use log::LevelFilter;
-use rand::rngs::ThreadRng;
-use rand::Rng;
 
 use simple_logger::SimpleLogger;
 
 const WINDOW_WIDTH: u32 = 1024;
 const WINDOW_HEIGHT: u32 = 768;
 
+const DEFAULT_LEVEL_SIZE: [u32; 2] = [64, 64];
+
+const DEFAULT_TILES: &str = "assets/tiles";
+const DEFAULT_TILE_SIZE: [u32; 2] = [16, 16];
+const DEFAULT_TILE_MANIFEST: &str = "assets/tiles.json";
+const DEFAULT_BINDINGS: &str = "assets/bindings.json";
+
+/// How far the player can see when exploring, in cells.
+const SIGHT_RADIUS: i32 = 8;
+
 static mut DELTA_TIME: f64 = 0.0;
 static mut LAST_TIME: f64 = 0.0;
 
@@ -95,36 +120,338 @@ extern "system" fn debug_callback(
     );
 }
 
-fn get_rand_ceiling_tile(rng: &mut ThreadRng) -> u32 {
-    let n = rng.gen_range(0..10);
-    if n > 8 {
-        return 24;
-    } else if n > 4 && n > 7 {
-        return 12;
+/// Point lights for every light-emitting tile in the level.
+fn level_lights(level: &Level) -> Vec<PointLight> {
+    let mut lights = Vec::new();
+    for y in 0..level.height() as i32 {
+        for x in 0..level.width() as i32 {
+            if let Some(light) = level.tile(x, y).and_then(|t| t.light) {
+                lights.push(PointLight {
+                    position: Vec3::new(x as f32, 0.0, y as f32),
+                    color: Vec3::from(light.color),
+                    radius: light.radius,
+                });
+            }
+        }
+    }
+    lights
+}
+
+/// Command line options.
+struct Args {
+    /// `--seed <n>`: rebuild a specific dungeon.
+    seed: Option<u64>,
+    /// `--size <w>x<h>`: level dimensions in cells.
+    size: [u32; 2],
+    /// `--generator <name>`: name of a registered level generator. Defaults
+    /// to the generator for the current depth.
+    generator: Option<String>,
+    /// `--depth <n>`: which floor of the dungeon to start on.
+    depth: u32,
+    /// `--connectivity <repair|regenerate>`: how to handle levels with
+    /// floor the spawn can't reach.
+    connectivity: ConnectivityPolicy,
+    /// `--map <file>`: start on a hand-authored map instead of a generated
+    /// floor, either ASCII or a Tiled `.tmx`/`.tmj`/`.json` map.
+    map: Option<String>,
+    /// `--export-map <file>`: write the starting floor out as an ASCII map.
+    export_map: Option<String>,
+    /// `--tiles <path>`: a folder of tile images, or a single tilesheet
+    /// image to slice into tiles.
+    tiles: String,
+    /// `--tile-size <w>x<h>`: size of the tiles in a tilesheet, in pixels.
+    tile_size: [u32; 2],
+    /// `--tile-manifest <file>`: tile definitions for the tile images.
+    /// Tilesheet tiles are named `<sheet file name>#<index>`.
+    tile_manifest: String,
+    /// `--greedy-mesh`: merge coplanar level faces into larger quads.
+    greedy_mesh: bool,
+    /// `--transition-time <seconds>`: how long a step or turn takes; 0
+    /// snaps instantly.
+    transition_time: f64,
+    /// `--turn <4|8>`: turn in 90° steps, or 45° steps with diagonals.
+    turn_mode: TurnMode,
+    /// `--free-look`: mouse-look and smooth WASD movement instead of
+    /// stepping between cells.
+    free_look: bool,
+    /// `--fov <degrees>`, `--near <distance>` and `--far <distance>`: the
+    /// vertical field of view and clipping planes.
+    projection: Projection,
+    /// `--bindings <file>`: which keys and mouse buttons trigger which
+    /// actions.
+    bindings: String,
+}
+
+fn parse_size(s: &str) -> Option<[u32; 2]> {
+    let (w, h) = s.split_once('x')?;
+    let size = [w.parse().ok()?, h.parse().ok()?];
+    if size[0] == 0 || size[1] == 0 {
+        return None;
+    }
+    Some(size)
+}
+
+fn parse_args() -> Args {
+    let mut args = Args {
+        seed: None,
+        size: DEFAULT_LEVEL_SIZE,
+        generator: None,
+        depth: 0,
+        connectivity: ConnectivityPolicy::Regenerate { max_attempts: 5 },
+        map: None,
+        export_map: None,
+        tiles: DEFAULT_TILES.to_string(),
+        tile_size: DEFAULT_TILE_SIZE,
+        tile_manifest: DEFAULT_TILE_MANIFEST.to_string(),
+        greedy_mesh: false,
+        transition_time: DEFAULT_TRANSITION_TIME,
+        turn_mode: TurnMode::Eight,
+        free_look: false,
+        projection: Projection::default(),
+        bindings: DEFAULT_BINDINGS.to_string(),
+    };
+    let mut iter = env::args().skip(1);
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--seed" => match iter.next().map(|s| s.parse::<u64>()) {
+                Some(Ok(seed)) => args.seed = Some(seed),
+                _ => log::warn!("--seed expects an unsigned integer, ignoring"),
+            },
+            "--size" => match iter.next().as_deref().and_then(parse_size) {
+                Some(size) => args.size = size,
+                None => log::warn!("--size expects <width>x<height>, ignoring"),
+            },
+            "--generator" => match iter.next() {
+                Some(name) => args.generator = Some(name),
+                None => log::warn!("--generator expects a name, ignoring"),
+            },
+            "--depth" => match iter.next().map(|s| s.parse::<u32>()) {
+                Some(Ok(depth)) => args.depth = depth,
+                _ => log::warn!("--depth expects an unsigned integer, ignoring"),
+            },
+            "--connectivity" => match iter.next().as_deref() {
+                Some("repair") => args.connectivity = ConnectivityPolicy::Repair,
+                Some("regenerate") => {
+                    args.connectivity = ConnectivityPolicy::Regenerate { max_attempts: 5 }
+                }
+                _ => log::warn!("--connectivity expects repair or regenerate, ignoring"),
+            },
+            "--map" => match iter.next() {
+                Some(path) => args.map = Some(path),
+                None => log::warn!("--map expects a file, ignoring"),
+            },
+            "--export-map" => match iter.next() {
+                Some(path) => args.export_map = Some(path),
+                None => log::warn!("--export-map expects a file, ignoring"),
+            },
+            "--tiles" => match iter.next() {
+                Some(path) => args.tiles = path,
+                None => log::warn!("--tiles expects a folder or image, ignoring"),
+            },
+            "--tile-size" => match iter.next().as_deref().and_then(parse_size) {
+                Some(size) => args.tile_size = size,
+                None => log::warn!("--tile-size expects <width>x<height>, ignoring"),
+            },
+            "--tile-manifest" => match iter.next() {
+                Some(path) => args.tile_manifest = path,
+                None => log::warn!("--tile-manifest expects a file, ignoring"),
+            },
+            "--greedy-mesh" => args.greedy_mesh = true,
+            "--transition-time" => match iter.next().map(|s| s.parse::<f64>()) {
+                Some(Ok(t)) if t >= 0.0 => args.transition_time = t,
+                _ => log::warn!("--transition-time expects seconds, ignoring"),
+            },
+            "--turn" => match iter.next().as_deref() {
+                Some("4") => args.turn_mode = TurnMode::Four,
+                Some("8") => args.turn_mode = TurnMode::Eight,
+                _ => log::warn!("--turn expects 4 or 8, ignoring"),
+            },
+            "--free-look" => args.free_look = true,
+            "--bindings" => match iter.next() {
+                Some(path) => args.bindings = path,
+                None => log::warn!("--bindings expects a file, ignoring"),
+            },
+            "--fov" => match iter.next().map(|s| s.parse::<f32>()) {
+                Some(Ok(fov)) if fov > 0.0 && fov < 180.0 => args.projection.fov = fov,
+                _ => log::warn!("--fov expects degrees between 0 and 180, ignoring"),
+            },
+            "--near" => match iter.next().map(|s| s.parse::<f32>()) {
+                Some(Ok(near)) if near > 0.0 => args.projection.near = near,
+                _ => log::warn!("--near expects a positive distance, ignoring"),
+            },
+            "--far" => match iter.next().map(|s| s.parse::<f32>()) {
+                Some(Ok(far)) if far > 0.0 => args.projection.far = far,
+                _ => log::warn!("--far expects a positive distance, ignoring"),
+            },
+            _ => log::warn!("unknown argument: {}", arg),
+        }
+    }
+    if args.projection.near >= args.projection.far {
+        log::warn!("--near must be less than --far, using the default clipping planes");
+        let Projection { near, far, .. } = Projection::default();
+        args.projection.near = near;
+        args.projection.far = far;
+    }
+    args
+}
+
+/// Loads the tile textures into `texture` and the tile manifest describing
+/// them.
+unsafe fn load_tiles(
+    texture: &TextureArray,
+    args: &Args,
+) -> Result<(HashMap<String, u32>, Rc<TileRegistry>), AssetError> {
+    let path = Path::new(&args.tiles);
+    let layers = if path.is_dir() {
+        texture.load(path)?
     } else {
-        return 0;
+        texture.load_sheet(path, args.tile_size)?
+    };
+    let tiles = TileRegistry::load(Path::new(&args.tile_manifest), &layers).map_err(|source| {
+        AssetError::TileManifest {
+            path: args.tile_manifest.clone(),
+            source,
+        }
+    })?;
+    Ok((layers, Rc::new(tiles)))
+}
+
+/// Loads a hand-authored level, picking the format from the extension.
+fn load_map(
+    path: &Path,
+    layers: &HashMap<String, u32>,
+    tiles: Rc<TileRegistry>,
+) -> Result<Level, AssetError> {
+    let name = path.display().to_string();
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("tmx") | Some("tmj") | Some("json") => Level::load_tiled(path, layers, tiles)
+            .map_err(|source| AssetError::TiledMap { path: name, source }),
+        _ => Level::load_ascii(path, tiles)
+            .map_err(|source| AssetError::AsciiMap { path: name, source }),
+    }
+}
+
+/// Unwraps a startup result, or stops the game with the reason it failed.
+/// Asset errors name the file at fault.
+fn or_exit<T, E: Display>(result: Result<T, E>) -> T {
+    match result {
+        Ok(value) => value,
+        Err(e) => {
+            log::error!("{}", e);
+            std::process::exit(1);
+        }
     }
 }
 
-fn get_rand_floor_tile(rng: &mut ThreadRng) -> u32 {
-    let n = rng.gen_range(0..10);
-    if n > 8 {
-        return 42;
-    } else if n > 4 && n <= 7 {
-        return 49;
+/// The forward and left movement asked for by the inputs held, each from -1
+/// to 1. Two inputs held for one action count once.
+fn free_look_input(held: &HashMap<Button, Action>) -> (f32, f32) {
+    let holds = |action| held.values().any(|&a| a == action) as i8 as f32;
+    let axis = |plus, minus| holds(plus) - holds(minus);
+    (
+        axis(Action::MoveForward, Action::MoveBack),
+        axis(Action::StrafeLeft, Action::StrafeRight),
+    )
+}
+
+/// Hides the cursor and keeps it in the window for mouse-look, or gives it
+/// back.
+fn grab_cursor(window: &Window, grab: bool) {
+    let result = if grab {
+        window
+            .set_cursor_grab(CursorGrabMode::Locked)
+            .or_else(|_| window.set_cursor_grab(CursorGrabMode::Confined))
     } else {
-        return 48;
+        window.set_cursor_grab(CursorGrabMode::None)
+    };
+    if let Err(e) = result {
+        log::warn!("cannot grab the cursor: {}", e);
+    }
+    window.set_cursor_visible(!grab);
+}
+
+/// Uses the door in front of the camera, otherwise the stairs underfoot.
+fn interact(
+    dungeon: &mut Dungeon,
+    camera: &mut Camera,
+    renderer: &mut Renderer,
+    animated: &mut Vec<usize>,
+    time: f64,
+    mesh_options: MeshOptions,
+) {
+    let [x, y] = camera.facing_cell();
+    let [cx, cy] = camera.cell();
+    match dungeon.level_mut().use_door(x, y) {
+        Some(DoorEvent::Locked) => log::info!("the door at {}, {} is locked", x, y),
+        Some(event) => {
+            log::info!("door at {}, {}: {:?}", x, y, event);
+            dungeon.level_mut().reveal(cx, cy, SIGHT_RADIUS);
+        }
+        None => match dungeon.level().stairs_at(cx, cy) {
+            Some(stairs) => match dungeon.take_stairs(stairs) {
+                Some(arrival) => {
+                    log::info!("took stairs {:?} to depth {}", stairs, dungeon.depth());
+                    camera.set_cell(arrival);
+                    renderer.set_chunks(&build_level_mesh(
+                        dungeon.level(),
+                        dungeon.decoration_seed(),
+                        time,
+                        mesh_options,
+                    ));
+                    *animated = animated_chunks(dungeon.level());
+                    dungeon.level_mut().take_dirty();
+                    renderer.set_lights(&level_lights(dungeon.level()));
+                    dungeon.level_mut().reveal(
+                        arrival[0] as i32,
+                        arrival[1] as i32,
+                        SIGHT_RADIUS,
+                    );
+                }
+                None => log::info!("these stairs lead nowhere"),
+            },
+            None => log::info!("nothing to use at {}, {}", x, y),
+        },
+    }
+}
+
+fn on_walk(event: WalkEvent, level: &mut Level) {
+    let to = match event {
+        WalkEvent::Moved { to } => {
+            log::info!("moved to {:?}", to);
+            to
+        }
+        WalkEvent::Deflected { to } => {
+            log::info!("deflected to {:?}", to);
+            to
+        }
+        WalkEvent::Bumped { wall } => {
+            log::info!("bumped into wall at {:?}", wall);
+            return;
+        }
+    };
+    for trigger in level.triggers_at(to[0], to[1]) {
+        log::info!("triggered {} {:?}", trigger.name, trigger.properties);
+    }
+    for (x, y) in level.unlock_from(to[0], to[1]) {
+        log::info!("found the key to the door at {}, {}", x, y);
     }
 }
 
 fn main() {
-    let mut rng = rand::thread_rng();
     SimpleLogger::new()
         .with_level(LevelFilter::Info)
         .env()
         .init()
         .unwrap();
     log::info!("starting gpthack");
+    let args = parse_args();
+    let seed = args.seed.unwrap_or_else(|| {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_nanos() as u64
+    });
+    log::info!("level seed: {} (replay with --seed {})", seed, seed);
     let event_loop = EventLoop::new();
     let primary_monitor = match event_loop.primary_monitor() {
         Some(m) => m,
@@ -163,38 +490,72 @@ fn main() {
     }
 
     let texture;
+    let (layers, tiles);
     unsafe {
         log::info!("loading tiles");
         texture = TextureArray::new();
-        texture.load(Path::new("assets/tiles"));
+        (layers, tiles) = or_exit(load_tiles(&texture, &args));
     }
 
-    let mut level = Level::new();
-    level.build();
-
-    let mut cubes: Vec<Cube> = Vec::new();
-    for y in 0..64 {
-        for x in 0..64 {
-            cubes.push(Cube::new(
-                Vec3::new(x as f32, 1.0, y as f32),
-                get_rand_ceiling_tile(&mut rng),
-            )); // ceiling
-            if level.data[y][x] > 0 {
-                cubes.push(Cube::new(
-                    Vec3::new(x as f32, 0.0, y as f32),
-                    level.data[y][x],
-                ));
-            }
-            cubes.push(Cube::new(
-                Vec3::new(x as f32, -1.0, y as f32),
-                get_rand_floor_tile(&mut rng),
-            )); // floor
+    let mut dungeon = Dungeon::new(
+        seed,
+        args.size,
+        tiles.clone(),
+        GeneratorRegistry::with_builtins(),
+        args.generator,
+        args.connectivity,
+        args.depth,
+    );
+    if let Some(path) = &args.map {
+        let level = or_exit(load_map(Path::new(path), &layers, tiles.clone()));
+        log::info!("loaded {}x{} map {}", level.width(), level.height(), path);
+        dungeon.set_level(level);
+    }
+    if let Some(path) = &args.export_map {
+        match dungeon.level().save_ascii(Path::new(path)) {
+            Ok(()) => log::info!("exported map to {}", path),
+            Err(e) => log::error!("cannot export map to {}: {}", path, e),
         }
     }
+    let spawn = dungeon.level().spawn;
+    dungeon.level_mut().reveal(spawn[0] as i32, spawn[1] as i32, SIGHT_RADIUS);
 
-    let mut camera = Camera::new(level.spawn);
-    let mut ui = UserInterface::new([WINDOW_HEIGHT, WINDOW_WIDTH]);
-    let mut renderer = Renderer::new(cubes, ui).expect("Cannot create renderer");
+    let mut camera = Camera::new(spawn);
+    camera.transition_time = args.transition_time;
+    camera.turn_mode = args.turn_mode;
+    if args.free_look {
+        camera.mode = CameraMode::Free;
+        grab_cursor(gl_context.window(), true);
+    }
+    let bindings_path = &args.bindings;
+    let bindings = or_exit(Bindings::load(Path::new(bindings_path)).map_err(|source| {
+        AssetError::Bindings {
+            path: bindings_path.clone(),
+            source,
+        }
+    }));
+    let mut modifiers = ModifiersState::empty();
+    let mut held_inputs = HashMap::new();
+    let mut focused = true;
+    let window_size = gl_context.window().inner_size();
+    let window_size = [window_size.width, window_size.height];
+    let mut ui = or_exit(UserInterface::new(window_size));
+    let start = Instant::now();
+    let mut animation_frames = tiles.animation_frames(0.0);
+    let mesh_options = MeshOptions {
+        greedy: args.greedy_mesh,
+    };
+    let chunks = build_level_mesh(dungeon.level(), dungeon.decoration_seed(), 0.0, mesh_options);
+    log::info!(
+        "level mesh: {} chunks, {} vertices, {} triangles",
+        chunks.len(),
+        chunks.iter().map(|c| c.mesh.vertex_count()).sum::<usize>(),
+        chunks.iter().map(|c| c.mesh.triangle_count()).sum::<usize>()
+    );
+    let mut renderer = or_exit(Renderer::new(&chunks, ui, args.projection, window_size));
+    let mut animated = animated_chunks(dungeon.level());
+    dungeon.level_mut().take_dirty();
+    renderer.set_lights(&level_lights(dungeon.level()));
 
     log::info!("starting game loop");
     event_loop.run(move |event, _, control_flow| {
@@ -203,71 +564,134 @@ fn main() {
         let next_frame_time = std::time::Instant::now() + std::time::Duration::from_nanos(0);
         *control_flow = glutin::event_loop::ControlFlow::WaitUntil(next_frame_time);
 
-        unsafe {
-            let current = SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .unwrap()
-                .as_secs_f64();
-            DELTA_TIME = current - LAST_TIME;
-            LAST_TIME = current;
-        }
+        let time = start.elapsed().as_secs_f64();
 
         match event {
             Event::LoopDestroyed => (),
-            Event::WindowEvent { event, .. } => match event {
-                WindowEvent::KeyboardInput {
-                    input:
-                        KeyboardInput {
-                            state: ElementState::Pressed,
-                            virtual_keycode: Some(glutin::event::VirtualKeyCode::Left),
-                            ..
-                        },
-                    ..
-                } => {
-                    log::info!("left?");
-                    camera.turn(1);
+            Event::WindowEvent { event, .. } => {
+                let input = match event {
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state,
+                                virtual_keycode: Some(key),
+                                ..
+                            },
+                        ..
+                    } => Some((Button::Key(key), state)),
+                    WindowEvent::MouseInput { state, button, .. } => {
+                        Some((Button::Mouse(button), state))
+                    }
+                    WindowEvent::ModifiersChanged(state) => {
+                        modifiers = state;
+                        None
+                    }
+                    WindowEvent::Focused(is_focused) => {
+                        if camera.mode == CameraMode::Free {
+                            grab_cursor(gl_context.window(), is_focused);
+                        }
+                        // releases while unfocused never arrive
+                        held_inputs.clear();
+                        focused = is_focused;
+                        None
+                    }
+                    WindowEvent::Resized(physical_size) => {
+                        gl_context.resize(physical_size);
+                        renderer.resize([physical_size.width, physical_size.height]);
+                        None
+                    }
+                    WindowEvent::CloseRequested => {
+                        *control_flow = ControlFlow::Exit;
+                        None
+                    }
+                    _ => None,
+                };
+                // held inputs remember their action, so a release ends it
+                // whatever modifiers are held by then
+                let pressed = match input {
+                    Some((button, ElementState::Released)) => {
+                        held_inputs.remove(&button);
+                        None
+                    }
+                    Some((button, ElementState::Pressed)) => {
+                        bindings.action(button, modifiers).map(|action| (button, action))
+                    }
+                    None => None,
+                };
+                if let Some((button, action)) = pressed {
+                    held_inputs.insert(button, action);
+                    match action {
+                        Action::MoveForward => camera.push_move(Move::Walk(1)),
+                        Action::MoveBack => camera.push_move(Move::Walk(-1)),
+                        Action::TurnLeft => camera.push_move(Move::Turn(1)),
+                        Action::TurnRight => camera.push_move(Move::Turn(-1)),
+                        Action::StrafeLeft => camera.push_move(Move::Strafe(1)),
+                        Action::StrafeRight => camera.push_move(Move::Strafe(-1)),
+                        Action::Interact => interact(
+                            &mut dungeon,
+                            &mut camera,
+                            &mut renderer,
+                            &mut animated,
+                            time,
+                            mesh_options,
+                        ),
+                        Action::OpenMap => log::info!(
+                            "explored map of depth {}:\n{}",
+                            dungeon.depth(),
+                            dungeon.level().explored_map(camera.cell())
+                        ),
+                    }
                 }
-                WindowEvent::KeyboardInput {
-                    input:
-                        KeyboardInput {
-                            state: ElementState::Pressed,
-                            virtual_keycode: Some(glutin::event::VirtualKeyCode::Right),
-                            ..
-                        },
-                    ..
-                } => {
-                    log::info!("right?");
-                    camera.turn(-1);
+            }
+            Event::DeviceEvent {
+                event: DeviceEvent::MouseMotion { delta: (dx, dy) },
+                ..
+            } if focused => camera.look_around(dx, dy),
+            Event::MainEventsCleared => {
+                // once per frame, so the delta covers the whole frame
+                let delta_time = unsafe {
+                    let current = SystemTime::now()
+                        .duration_since(UNIX_EPOCH)
+                        .unwrap()
+                        .as_secs_f64();
+                    DELTA_TIME = current - LAST_TIME;
+                    LAST_TIME = current;
+                    DELTA_TIME
+                };
+                let walked = match camera.mode {
+                    CameraMode::Grid => camera.update(delta_time, dungeon.level()),
+                    CameraMode::Free => {
+                        let (forward, left) = free_look_input(&held_inputs);
+                        camera.free_move(forward, left, delta_time, dungeon.level())
+                    }
+                };
+                if let Some(event) = walked {
+                    on_walk(event, dungeon.level_mut());
+                    let [x, y] = camera.cell();
+                    dungeon.level_mut().reveal(x, y, SIGHT_RADIUS);
                 }
-                WindowEvent::KeyboardInput {
-                    input:
-                        KeyboardInput {
-                            state: ElementState::Pressed,
-                            virtual_keycode: Some(glutin::event::VirtualKeyCode::Up),
-                            ..
-                        },
-                    ..
-                } => {
-                    log::info!("up?");
-                    camera.walk(1);
+                let frames = tiles.animation_frames(time);
+                if frames != animation_frames {
+                    animation_frames = frames;
+                    // only chunks with animated tiles look any different
+                    renderer.update_chunks(&rebuild_chunks(
+                        dungeon.level(),
+                        dungeon.decoration_seed(),
+                        time,
+                        mesh_options,
+                        &animated,
+                    ));
                 }
-                WindowEvent::KeyboardInput {
-                    input:
-                        KeyboardInput {
-                            state: ElementState::Pressed,
-                            virtual_keycode: Some(glutin::event::VirtualKeyCode::Down),
-                            ..
-                        },
-                    ..
-                } => {
-                    log::info!("down?");
-                    camera.walk(-1);
+                // cells changed this frame, e.g. a door swinging open
+                if let Some(area) = dungeon.level_mut().take_dirty() {
+                    renderer.update_chunks(&rebuild_level_mesh(
+                        dungeon.level(),
+                        dungeon.decoration_seed(),
+                        time,
+                        mesh_options,
+                        &area,
+                    ));
                 }
-                WindowEvent::Resized(physical_size) => gl_context.resize(physical_size),
-                WindowEvent::CloseRequested => *control_flow = ControlFlow::Exit,
-                _ => (),
-            },
-            Event::MainEventsCleared => {
                 gl_context.window().request_redraw();
             }
             Event::RedrawRequested(_) => {
@@ -64,58 +64,110 @@ const CUBE_VERTICES: [f32; 72] = [
      0.5,  0.5,  0.5,
 ];
 
+/// Texture array layers for the faces of a cube.
+#[derive(Debug, Clone, Copy)]
+pub struct FaceTextures {
+    pub side: u32,
+    pub top: u32,
+    pub bottom: u32,
+}
+
+impl FaceTextures {
+    /// The same texture on every face.
+    pub fn all(texture_id: u32) -> Self {
+        Self {
+            side: texture_id,
+            top: texture_id,
+            bottom: texture_id,
+        }
+    }
+}
+
+/// The faces of a cube, in the order `CUBE_VERTICES` lists them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CubeFace {
+    Front,
+    Top,
+    Back,
+    Bottom,
+    Left,
+    Right,
+}
+
+impl CubeFace {
+    pub const ALL: [CubeFace; 6] = [
+        CubeFace::Front,
+        CubeFace::Top,
+        CubeFace::Back,
+        CubeFace::Bottom,
+        CubeFace::Left,
+        CubeFace::Right,
+    ];
+
+    fn texture(self, faces: FaceTextures) -> u32 {
+        match self {
+            CubeFace::Top => faces.top,
+            CubeFace::Bottom => faces.bottom,
+            _ => faces.side,
+        }
+    }
+}
+
 pub struct Cube {
-    loc: Vec3,
     geom: [f32; 144],
-    texture_id: u32,
 }
 
-// interleave cube verts with texture coords
+/// The four vertices of one face of a box, interleaved with their texture
+/// coordinates and texture array layer.
 #[rustfmt::skip]
-fn get_vertices(loc: Vec3, tex_id: u32) -> [f32; 144] {
-    let mut x:[f32; 144] = [0.0; 144];
-    let mut i = 0;
-    while i < 24 {
+pub fn face_vertices(loc: Vec3, size: Vec3, face: CubeFace, texture_id: u32) -> [f32; 24] {
+    let mut x: [f32; 24] = [0.0; 24];
+    for i in 0..4 {
         let xi = i * 6;
-        let vi = i * 3;
-        let ti = (i % 4) * 2;
+        let vi = (face as usize * 4 + i) * 3;
+        let ti = i * 2;
 
         // cube vertices
-        x[xi]   = CUBE_VERTICES[vi]   + loc[0];
-        x[xi+1] = CUBE_VERTICES[vi+1] + loc[1];
-        x[xi+2] = CUBE_VERTICES[vi+2] + loc[2];
+        x[xi]   = CUBE_VERTICES[vi]   * size[0] + loc[0];
+        x[xi+1] = CUBE_VERTICES[vi+1] * size[1] + loc[1];
+        x[xi+2] = CUBE_VERTICES[vi+2] * size[2] + loc[2];
 
         // texture coordinates
         x[xi+3] = TEXTURE_COORDS[ti];
         x[xi+4] = TEXTURE_COORDS[ti+1];
 
         // texture array index
-        x[xi+5] = tex_id as f32;
-
-        i += 1;
+        x[xi+5] = texture_id as f32;
+    }
+    x
+}
 
-        // log::info!("x: {}, {}, {}, {}, {}", x[xi], x[xi + 1], x[xi + 2], x[xi + 3], x[xi + 4]);
+// interleave cube verts with texture coords
+fn get_vertices(loc: Vec3, size: Vec3, faces: FaceTextures) -> [f32; 144] {
+    let mut x: [f32; 144] = [0.0; 144];
+    for (i, face) in CubeFace::ALL.iter().enumerate() {
+        let vertices = face_vertices(loc, size, *face, face.texture(faces));
+        x[i * 24..(i + 1) * 24].copy_from_slice(&vertices);
     }
-    log::debug!("get_vertices: {}", format!("{:?}", x));
-    return x;
+    log::debug!("get_vertices: {:?}", x);
+    x
 }
 
 impl Cube {
-    pub fn new(loc: Vec3, texture_id: u32) -> Self {
+    /// A box centered on `loc` with the given edge lengths, e.g. a thin
+    /// slab for a door.
+    pub fn with_size(loc: Vec3, size: Vec3, texture_id: u32) -> Self {
+        Self::with_faces(loc, size, FaceTextures::all(texture_id))
+    }
+
+    /// A box with its own texture on the sides, top and bottom.
+    pub fn with_faces(loc: Vec3, size: Vec3, faces: FaceTextures) -> Self {
         log::debug!("new cube");
-        let g = get_vertices(loc, texture_id);
-        Self {
-            loc,
-            geom: g,
-            texture_id,
-        }
+        let g = get_vertices(loc, size, faces);
+        Self { geom: g }
     }
 
     pub fn geom(&self) -> [f32; 144] {
         return self.geom;
     }
-
-    pub fn loc(&self) -> Vec3 {
-        return self.loc;
-    }
 }
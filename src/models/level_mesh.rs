@@ -0,0 +1,526 @@
+use glam::Vec3;
+use rand::SeedableRng;
+
+use crate::level::door::{Door, DoorAxis, DoorState};
+use crate::level::level::{Level, LevelRng, Rect};
+use crate::models::cube::{face_vertices, Cube, CubeFace};
+
+/// Door slabs are this thick, in cells.
+const DOOR_THICKNESS: f32 = 0.15;
+
+/// Level geometry ready for the renderer: interleaved position, texture
+/// coordinate and layer vertices, drawn as indexed triangles.
+#[derive(Default)]
+pub struct LevelMesh {
+    vertices: Vec<f32>,
+    indices: Vec<i32>,
+}
+
+impl LevelMesh {
+    /// Adds a quad given as the four vertices of a cube face.
+    pub fn add_face(&mut self, vertices: &[f32; 24]) {
+        let first = self.vertex_count() as i32;
+        self.vertices.extend_from_slice(vertices);
+        self.indices
+            .extend([0, 1, 2, 2, 3, 0].iter().map(|i| first + i));
+    }
+
+    pub fn add_cube(&mut self, cube: &Cube) {
+        for face in cube.geom().chunks_exact(24) {
+            let mut vertices = [0.0; 24];
+            vertices.copy_from_slice(face);
+            self.add_face(&vertices);
+        }
+    }
+
+    pub fn vertices(&self) -> &[f32] {
+        &self.vertices
+    }
+
+    pub fn indices(&self) -> &[i32] {
+        &self.indices
+    }
+
+    pub fn vertex_count(&self) -> usize {
+        self.vertices.len() / 6
+    }
+
+    pub fn triangle_count(&self) -> usize {
+        self.indices.len() / 3
+    }
+}
+
+/// A closed door spans the middle of its cell; an open one is swung back
+/// flat against the wall beside the passage.
+fn door_cube(x: i32, y: i32, door: &Door, texture_id: u32) -> Cube {
+    let (x, y) = (x as f32, y as f32);
+    let swing = 0.5 - DOOR_THICKNESS / 2.0 - 0.005;
+    let (loc, size) = match (door.axis, door.state) {
+        (DoorAxis::X, DoorState::Open) => (
+            Vec3::new(x, 0.0, y - swing),
+            Vec3::new(1.0, 1.0, DOOR_THICKNESS),
+        ),
+        (DoorAxis::X, _) => (Vec3::new(x, 0.0, y), Vec3::new(DOOR_THICKNESS, 1.0, 1.0)),
+        (DoorAxis::Y, DoorState::Open) => (
+            Vec3::new(x - swing, 0.0, y),
+            Vec3::new(DOOR_THICKNESS, 1.0, 1.0),
+        ),
+        (DoorAxis::Y, _) => (Vec3::new(x, 0.0, y), Vec3::new(1.0, 1.0, DOOR_THICKNESS)),
+    };
+    Cube::with_size(loc, size, texture_id)
+}
+
+/// Cells the player can stand in, which get a floor and ceiling; every other
+/// cell is a solid block.
+fn is_open(level: &Level, x: i32, y: i32) -> bool {
+    level.tile(x, y).is_some_and(|t| t.walkable) || level.door(x, y).is_some()
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MeshOptions {
+    /// Merge neighbouring faces that lie in the same plane and share a
+    /// texture into one quad, with the texture repeating once per cell.
+    pub greedy: bool,
+}
+
+/// A face of a `size` box whose texture repeats once per cell instead of
+/// stretching across the whole face.
+fn tiled_face(loc: Vec3, size: Vec3, face: CubeFace, texture_id: u32) -> [f32; 24] {
+    let mut vertices = face_vertices(loc, size, face, texture_id);
+    let (u, v) = match face {
+        CubeFace::Top | CubeFace::Bottom => (size.x, size.z),
+        CubeFace::Front | CubeFace::Back => (size.x, size.y),
+        CubeFace::Left | CubeFace::Right => (size.z, size.y),
+    };
+    for vertex in vertices.chunks_exact_mut(6) {
+        vertex[3] *= u;
+        vertex[4] *= v;
+    }
+    vertices
+}
+
+/// Textures of one kind of face over an area of the level, by cell. Faces
+/// that are hidden are `None`.
+struct FaceGrid {
+    /// `[x0, y0, x1, y1)` in level cells.
+    area: [usize; 4],
+    textures: Vec<Option<u32>>,
+}
+
+impl FaceGrid {
+    fn new(area: [usize; 4]) -> Self {
+        let [x0, y0, x1, y1] = area;
+        Self {
+            area,
+            textures: vec![None; (x1 - x0) * (y1 - y0)],
+        }
+    }
+
+    fn index(&self, x: usize, y: usize) -> usize {
+        let [x0, y0, x1, _] = self.area;
+        (y - y0) * (x1 - x0) + x - x0
+    }
+
+    fn set(&mut self, x: i32, y: i32, texture_id: u32) {
+        let i = self.index(x as usize, y as usize);
+        self.textures[i] = Some(texture_id);
+    }
+
+    /// Splits the faces within `[x0, y0, x1, y1)` into rectangles of one
+    /// texture, returned as `(x, y, width, height, texture)` in cells.
+    /// Rectangles grow along x and y only where allowed; with neither, every
+    /// face stands alone.
+    fn rects(
+        &self,
+        area: [usize; 4],
+        along_x: bool,
+        along_y: bool,
+    ) -> Vec<(usize, usize, usize, usize, u32)> {
+        let [x0, y0, x1, y1] = area;
+        let mut used = vec![false; self.textures.len()];
+        let free = |used: &[bool], x: usize, y: usize, texture: u32| {
+            let i = self.index(x, y);
+            !used[i] && self.textures[i] == Some(texture)
+        };
+        let mut rects = Vec::new();
+        for y in y0..y1 {
+            for x in x0..x1 {
+                let i = self.index(x, y);
+                let texture = match self.textures[i] {
+                    Some(texture) if !used[i] => texture,
+                    _ => continue,
+                };
+                let mut w = 1;
+                while along_x && x + w < x1 && free(&used, x + w, y, texture) {
+                    w += 1;
+                }
+                let mut h = 1;
+                while along_y && y + h < y1 && (x..x + w).all(|rx| free(&used, rx, y + h, texture))
+                {
+                    h += 1;
+                }
+                for ry in y..y + h {
+                    for rx in x..x + w {
+                        used[self.index(rx, ry)] = true;
+                    }
+                }
+                rects.push((x, y, w, h, texture));
+            }
+        }
+        rects
+    }
+}
+
+/// Cells along each side of a chunk.
+pub const CHUNK_SIZE: usize = 16;
+
+/// The side faces of a solid block and the neighbour each one looks onto.
+const SIDES: [(CubeFace, i32, i32); 4] = [
+    (CubeFace::Front, 0, 1),
+    (CubeFace::Back, 0, -1),
+    (CubeFace::Left, -1, 0),
+    (CubeFace::Right, 1, 0),
+];
+
+/// The geometry of one `CHUNK_SIZE` square of cells and the box around it.
+pub struct ChunkMesh {
+    pub min: Vec3,
+    pub max: Vec3,
+    pub mesh: LevelMesh,
+}
+
+/// Every visible face in an area of the level, gathered before they are
+/// split into chunks.
+struct LevelFaces {
+    /// `[x0, y0, x1, y1)` in level cells, whole chunks.
+    area: [usize; 4],
+    floors: FaceGrid,
+    ceilings: FaceGrid,
+    /// One grid per entry in `SIDES`.
+    walls: Vec<FaceGrid>,
+    doors: Vec<([usize; 2], Cube)>,
+}
+
+/// The decoration stream of cell (x, y), so a cell looks the same whichever
+/// other cells are meshed with it.
+fn cell_stream(x: i32, y: i32) -> u64 {
+    (y as u64) << 32 | x as u64
+}
+
+/// Gathers the faces of the cells in `area`. Whether a face is hidden is
+/// read from the level, so cells just outside the area need no gathering.
+fn collect_faces(level: &Level, seed: u64, time: f64, area: [usize; 4]) -> LevelFaces {
+    let mut faces = LevelFaces {
+        area,
+        floors: FaceGrid::new(area),
+        ceilings: FaceGrid::new(area),
+        walls: SIDES.iter().map(|_| FaceGrid::new(area)).collect(),
+        doors: Vec::new(),
+    };
+
+    let decoration = LevelRng::seed_from_u64(seed);
+    let [x0, y0, x1, y1] = area;
+    for y in y0 as i32..y1 as i32 {
+        for x in x0 as i32..x1 as i32 {
+            let tile = match level.tile(x, y) {
+                Some(tile) => tile,
+                None => continue,
+            };
+            let mut rng = decoration.clone();
+            rng.set_stream(cell_stream(x, y));
+            if is_open(level, x, y) {
+                // textures are picked even for hidden faces, so the rng is
+                // used the same way whatever gets culled
+                faces.ceilings.set(x, y, tile.top(&mut rng));
+                if let Some(door) = level.door(x, y) {
+                    let cube = door_cube(x, y, door, tile.side(&mut rng, time));
+                    faces.doors.push(([x as usize, y as usize], cube));
+                }
+                faces.floors.set(x, y, tile.bottom(&mut rng));
+            } else {
+                let side = tile.side(&mut rng, time);
+                tile.top(&mut rng);
+                tile.bottom(&mut rng);
+                for (grid, &(_, dx, dy)) in faces.walls.iter_mut().zip(SIDES.iter()) {
+                    if is_open(level, x + dx, y + dy) {
+                        grid.set(x, y, side);
+                    }
+                }
+            }
+        }
+    }
+    faces
+}
+
+impl LevelFaces {
+    /// Meshes the chunk whose first cell is `(x0, y0)`.
+    fn chunk(&self, x0: usize, y0: usize, options: MeshOptions) -> ChunkMesh {
+        let x1 = (x0 + CHUNK_SIZE).min(self.area[2]);
+        let y1 = (y0 + CHUNK_SIZE).min(self.area[3]);
+        let area = [x0, y0, x1, y1];
+        let mut mesh = LevelMesh::default();
+
+        for ([x, y], cube) in &self.doors {
+            if (x0..x1).contains(x) && (y0..y1).contains(y) {
+                mesh.add_cube(cube);
+            }
+        }
+
+        // floors and ceilings merge across the plane, walls only along
+        // their own row or column since they are one cell tall
+        let greedy = options.greedy;
+        let mut planes = vec![
+            (&self.floors, CubeFace::Top, -1.0, greedy, greedy),
+            (&self.ceilings, CubeFace::Bottom, 1.0, greedy, greedy),
+        ];
+        for (grid, &(face, _, _)) in self.walls.iter().zip(SIDES.iter()) {
+            let along_x = matches!(face, CubeFace::Front | CubeFace::Back);
+            planes.push((grid, face, 0.0, greedy && along_x, greedy && !along_x));
+        }
+        for (grid, face, level_y, along_x, along_y) in planes {
+            for (x, y, w, h, texture) in grid.rects(area, along_x, along_y) {
+                let (w, h) = (w as f32, h as f32);
+                let center = Vec3::new(
+                    x as f32 + (w - 1.0) / 2.0,
+                    level_y,
+                    y as f32 + (h - 1.0) / 2.0,
+                );
+                let size = Vec3::new(w, 1.0, h);
+                mesh.add_face(&tiled_face(center, size, face, texture));
+            }
+        }
+
+        ChunkMesh {
+            min: Vec3::new(x0 as f32 - 0.5, -0.5, y0 as f32 - 0.5),
+            max: Vec3::new(x1 as f32 - 0.5, 0.5, y1 as f32 - 0.5),
+            mesh,
+        }
+    }
+}
+
+/// Builds the level's geometry in `CHUNK_SIZE` chunks, row by row, keeping
+/// only the faces that border open space: the tops of floors, the
+/// undersides of ceilings and the sides of solid blocks facing a walkable
+/// cell. Doors keep their whole slab. See `MeshOptions` for merging the
+/// faces that are left; merged faces stop at chunk borders. Decoration
+/// comes from `seed`, which should be the floor's own decoration seed so
+/// every rebuild looks the same. `time` picks animation frames.
+pub fn build_level_mesh(
+    level: &Level,
+    seed: u64,
+    time: f64,
+    options: MeshOptions,
+) -> Vec<ChunkMesh> {
+    let (width, height) = (level.width() as usize, level.height() as usize);
+    let faces = collect_faces(level, seed, time, [0, 0, width, height]);
+    let mut chunks = Vec::new();
+    for y0 in (0..height).step_by(CHUNK_SIZE) {
+        for x0 in (0..width).step_by(CHUNK_SIZE) {
+            chunks.push(faces.chunk(x0, y0, options));
+        }
+    }
+    chunks
+}
+
+/// Re-meshes just the chunks a change to the cells in `area` can show up in,
+/// returning each with its index in `build_level_mesh`'s output. Cells next
+/// to the area count too, since their walls face into it. Only the cells of
+/// those chunks are visited, and each cell's decoration is its own, so the
+/// chunks match a full rebuild.
+pub fn rebuild_level_mesh(
+    level: &Level,
+    seed: u64,
+    time: f64,
+    options: MeshOptions,
+    area: &Rect,
+) -> Vec<(usize, ChunkMesh)> {
+    let (width, height) = (level.width() as usize, level.height() as usize);
+    if width == 0 || height == 0 {
+        return Vec::new();
+    }
+    let chunks_x = width.div_ceil(CHUNK_SIZE);
+    let first = |v: i32, max: usize| (v - 1).clamp(0, max as i32 - 1) as usize / CHUNK_SIZE;
+    let last = |v: i32, max: usize| (v + 1).clamp(0, max as i32 - 1) as usize / CHUNK_SIZE;
+    let (cx0, cx1) = (first(area.x1, width), last(area.x2, width));
+    let (cy0, cy1) = (first(area.y1, height), last(area.y2, height));
+
+    let cells = [
+        cx0 * CHUNK_SIZE,
+        cy0 * CHUNK_SIZE,
+        ((cx1 + 1) * CHUNK_SIZE).min(width),
+        ((cy1 + 1) * CHUNK_SIZE).min(height),
+    ];
+    let faces = collect_faces(level, seed, time, cells);
+    let mut chunks = Vec::new();
+    for cy in cy0..=cy1 {
+        for cx in cx0..=cx1 {
+            let mesh = faces.chunk(cx * CHUNK_SIZE, cy * CHUNK_SIZE, options);
+            chunks.push((cy * chunks_x + cx, mesh));
+        }
+    }
+    chunks
+}
+
+/// Indices in `build_level_mesh`'s output of the chunks holding an animated
+/// tile. Only these change when the animation frames move on.
+pub fn animated_chunks(level: &Level) -> Vec<usize> {
+    let (width, height) = (level.width() as usize, level.height() as usize);
+    let chunks_x = width.div_ceil(CHUNK_SIZE);
+    let mut chunks = Vec::new();
+    for y in 0..height {
+        for x in 0..width {
+            if level.tile(x as i32, y as i32).is_some_and(|t| t.animation.is_some()) {
+                chunks.push(y / CHUNK_SIZE * chunks_x + x / CHUNK_SIZE);
+            }
+        }
+    }
+    chunks.sort_unstable();
+    chunks.dedup();
+    chunks
+}
+
+/// Re-meshes the chunks with the given indices in `build_level_mesh`'s
+/// output, visiting only their own cells.
+pub fn rebuild_chunks(
+    level: &Level,
+    seed: u64,
+    time: f64,
+    options: MeshOptions,
+    chunks: &[usize],
+) -> Vec<(usize, ChunkMesh)> {
+    let (width, height) = (level.width() as usize, level.height() as usize);
+    let chunks_x = width.div_ceil(CHUNK_SIZE);
+    chunks
+        .iter()
+        .map(|&i| {
+            let (x0, y0) = (i % chunks_x * CHUNK_SIZE, i / chunks_x * CHUNK_SIZE);
+            let cells = [x0, y0, (x0 + CHUNK_SIZE).min(width), (y0 + CHUNK_SIZE).min(height)];
+            (i, collect_faces(level, seed, time, cells).chunk(x0, y0, options))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::level::generator::GeneratorRegistry;
+    use crate::level::tiles::{test_layers, test_registry, TileRegistry};
+
+    fn map(text: &str) -> Level {
+        Level::from_ascii(text, test_registry()).unwrap()
+    }
+
+    /// Total (vertices, triangles) over every chunk.
+    fn counts(level: &Level, options: MeshOptions) -> (usize, usize) {
+        let chunks = build_level_mesh(level, 0, 0.0, options);
+        chunks.iter().fold((0, 0), |(v, t), c| {
+            (v + c.mesh.vertex_count(), t + c.mesh.triangle_count())
+        })
+    }
+
+    #[test]
+    fn room_keeps_only_faces_bordering_open_space() {
+        let level = map("#####\n#@..#\n#...#\n#...#\n#####\n");
+        // 9 floors, 9 ceilings and the 12 wall sides facing into the room
+        let faces = 9 + 9 + 12;
+        assert_eq!(counts(&level, MeshOptions::default()), (faces * 4, faces * 2));
+    }
+
+    #[test]
+    fn doors_keep_their_whole_slab() {
+        // 5 floors, 5 ceilings, 12 wall sides, and the door's 6 faces
+        let faces = 5 + 5 + 12 + 6;
+        let mut level = map("#######\n#@.+..#\n#######\n");
+        assert_eq!(counts(&level, MeshOptions::default()), (faces * 4, faces * 2));
+        level.use_door(3, 1);
+        assert!(level.door(3, 1).is_some_and(|d| d.state == DoorState::Open));
+        assert_eq!(counts(&level, MeshOptions::default()), (faces * 4, faces * 2));
+    }
+
+    #[test]
+    fn culled_mesh_is_far_smaller_than_a_cube_per_cell() {
+        let generator = GeneratorRegistry::with_builtins();
+        let mut level = Level::new(64, 64, test_registry());
+        level.generate(generator.get("rooms").unwrap(), &mut LevelRng::seed_from_u64(3));
+
+        // what every cell used to cost: a cube for a solid cell, floor and
+        // ceiling cubes for an open one, and one more for a door
+        let mut cubes = 0;
+        for y in 0..64 {
+            for x in 0..64 {
+                cubes += match (is_open(&level, x, y), level.door(x, y).is_some()) {
+                    (false, _) => 1,
+                    (true, false) => 2,
+                    (true, true) => 3,
+                };
+            }
+        }
+        let (vertices, triangles) = counts(&level, MeshOptions::default());
+        assert!(vertices * 10 < cubes * 24, "{} vertices vs {} cubes", vertices, cubes);
+        assert_eq!(triangles * 2, vertices);
+
+        let (greedy, _) = counts(&level, MeshOptions { greedy: true });
+        assert!(greedy < vertices);
+    }
+
+    #[test]
+    fn partial_rebuild_matches_full_rebuild() {
+        let generator = GeneratorRegistry::with_builtins();
+        // not a whole number of chunks, so the edge chunks are partial
+        let mut level = Level::new(70, 50, test_registry());
+        level.generate(generator.get("bsp").unwrap(), &mut LevelRng::seed_from_u64(7));
+        level.take_dirty();
+        let (x, y) = (0..50)
+            .flat_map(|y| (0..70).map(move |x| (x, y)))
+            .find(|&(x, y)| level.door(x, y).is_some_and(|d| d.state == DoorState::Closed))
+            .unwrap();
+        level.use_door(x, y);
+        let area = level.take_dirty().unwrap();
+
+        for greedy in [false, true] {
+            let options = MeshOptions { greedy };
+            let full = build_level_mesh(&level, 5, 0.0, options);
+            let part = rebuild_level_mesh(&level, 5, 0.0, options, &area);
+            assert!(!part.is_empty() && part.len() < full.len());
+            for (i, chunk) in &part {
+                assert_eq!(chunk.mesh.vertices(), full[*i].mesh.vertices());
+                assert_eq!(chunk.mesh.indices(), full[*i].mesh.indices());
+                assert_eq!((chunk.min, chunk.max), (full[*i].min, full[*i].max));
+            }
+        }
+    }
+
+    #[test]
+    fn animation_rebuilds_only_animated_chunks() {
+        let manifest = r#"{"tiles": [
+            { "name": "floor", "walkable": true, "top": "tile_0000.png",
+              "bottom": "tile_0048.png" },
+            { "name": "wall", "side": "tile_0040.png" },
+            { "name": "door", "side": "tile_0045.png" },
+            { "name": "stairs_down", "side": "tile_0036.png" },
+            { "name": "stairs_up", "side": "tile_0039.png" },
+            { "name": "torch", "animation": {
+                "frames": ["tile_0040.png", "tile_0041.png"], "frame_time": 0.5 } }
+        ]}"#;
+        let tiles = Rc::new(TileRegistry::parse(manifest, &test_layers()).unwrap());
+        // 40x20 cells, three chunks by two, with a torch in the second chunk
+        let mut rows = vec!["#".repeat(40); 20];
+        rows[1] = format!("#@{}#", ".".repeat(36));
+        rows[2] = format!("#{}t{}#", ".".repeat(19), ".".repeat(18));
+        let map = format!("[legend]\nt = torch\n[map]\n{}", rows.join("\n"));
+        let level = Level::from_ascii(&map, tiles).unwrap();
+        assert_eq!(animated_chunks(&level), vec![1]);
+
+        let options = MeshOptions::default();
+        let full = build_level_mesh(&level, 9, 0.75, options);
+        let part = rebuild_chunks(&level, 9, 0.75, options, &[1]);
+        assert_eq!(part.len(), 1);
+        assert_eq!(part[0].1.mesh.vertices(), full[1].mesh.vertices());
+        assert_eq!(part[0].1.mesh.indices(), full[1].mesh.indices());
+        let before = build_level_mesh(&level, 9, 0.0, options);
+        assert_ne!(before[1].mesh.vertices(), full[1].mesh.vertices());
+        assert_eq!(before[0].mesh.vertices(), full[0].mesh.vertices());
+    }
+}
@@ -1 +1,2 @@
-pub mod cube;
\ No newline at end of file
+pub mod cube;
+pub mod level_mesh;
\ No newline at end of file
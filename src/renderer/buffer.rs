@@ -3,6 +3,8 @@ use gl::types::*;
 pub struct Buffer {
     pub id: GLuint,
     target: GLuint,
+    /// Bytes of storage allocated on the GPU.
+    size: usize,
 }
 
 impl Drop for Buffer {
@@ -18,10 +20,14 @@ impl Buffer {
         let mut id: GLuint = 0;
         gl::GenBuffers(1, &mut id);
         log::info!("created buffer - id: {}, target: {}", id, target);
-        Self { id, target }
+        Self {
+            id,
+            target,
+            size: 0,
+        }
     }
 
-    pub unsafe fn set_data<D>(&self, data: &[D], usage: GLuint) {
+    pub unsafe fn set_data<D>(&mut self, data: &[D], usage: GLuint) {
         self.bind();
         let (_, data_bytes, _) = data.align_to::<u8>();
         gl::BufferData(
@@ -30,9 +36,38 @@ impl Buffer {
             data_bytes.as_ptr() as *const _,
             usage,
         );
+        self.size = data_bytes.len();
         // self.unbind();
     }
 
+    /// Overwrites part of the buffer, starting `offset` bytes in. The data
+    /// has to fit in the storage already allocated.
+    pub unsafe fn set_sub_data<D>(&self, offset: usize, data: &[D]) {
+        self.bind();
+        let (_, data_bytes, _) = data.align_to::<u8>();
+        debug_assert!(offset + data_bytes.len() <= self.size);
+        gl::BufferSubData(
+            self.target,
+            offset as GLintptr,
+            data_bytes.len() as GLsizeiptr,
+            data_bytes.as_ptr() as *const _,
+        );
+    }
+
+    /// Replaces the contents of a buffer that gets updated often. Storage
+    /// that is big enough is orphaned and refilled instead of reallocated,
+    /// so the upload doesn't wait on draws still reading the old data.
+    pub unsafe fn stream_data<D>(&mut self, data: &[D], usage: GLuint) {
+        let bytes = std::mem::size_of_val(data);
+        if bytes > self.size {
+            self.set_data(data, usage);
+            return;
+        }
+        self.bind();
+        gl::BufferData(self.target, self.size as GLsizeiptr, std::ptr::null(), usage);
+        self.set_sub_data(0, data);
+    }
+
     pub unsafe fn bind(&self) {
         gl::BindBuffer(self.target, self.id);
     }
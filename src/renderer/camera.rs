@@ -1,15 +1,109 @@
+use std::collections::VecDeque;
+
 use glam::Vec3;
 
+use crate::level::level::Level;
+
+/// What happened when the camera tried to walk one cell.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WalkEvent {
+    /// Moved into the target cell.
+    Moved { to: [i32; 2] },
+    /// The diagonal target was a wall, so the move slid along it into an
+    /// open orthogonal neighbour instead.
+    Deflected { to: [i32; 2] },
+    /// Walked into a wall (or a closed corner); the camera did not move.
+    Bumped { wall: [i32; 2] },
+}
+
+/// A move the player asked for. Moves made while the camera is still
+/// moving wait their turn.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Move {
+    /// One cell forward (1) or back (-1).
+    Walk(i8),
+    /// One turn step left (1) or right (-1), see `TurnMode`.
+    Turn(i8),
+    /// One cell sideways to the left (1) or right (-1), keeping the facing.
+    Strafe(i8),
+}
+
+/// How far one turn goes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TurnMode {
+    /// 90° turns, so the camera only ever faces along the grid.
+    Four,
+    /// 45° turns, allowing diagonal facings.
+    Eight,
+}
+
+/// Whether the camera steps between cells or moves freely.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CameraMode {
+    /// Steps and turns one cell or facing at a time, see `Move`.
+    Grid,
+    /// Mouse-look and smooth movement, sliding along walls.
+    Free,
+}
+
+/// Free movement speed, in cells per second.
+const FREE_SPEED: f32 = 3.0;
+
+/// How close the camera gets to a wall in free mode, in cells.
+const FREE_RADIUS: f32 = 0.2;
+
+/// Longest distance free movement covers in one go, in cells. Longer moves,
+/// from a slow frame, are split up so they can't skip over a wall.
+const FREE_MAX_STEP: f32 = 0.25;
+
+/// Radians turned per pixel of mouse movement.
+const MOUSE_SENSITIVITY: f32 = 0.003;
+
+/// Pitch stops just short of straight up or down, where the view would
+/// flip.
+const MAX_PITCH: f32 = 1.5;
+
+/// How many moves can wait while a step or turn plays out. Anything more is
+/// dropped, so holding a key doesn't leave the camera moving long after.
+const MAX_QUEUED_MOVES: usize = 2;
+
+/// Seconds a step or turn takes by default.
+pub const DEFAULT_TRANSITION_TIME: f64 = 0.15;
+
+/// A step or turn being played out.
+struct Transition {
+    from_pos: Vec3,
+    to_pos: Vec3,
+    from_ang: f32,
+    to_ang: f32,
+    elapsed: f64,
+}
+
 pub struct Camera {
     pub pos: Vec3,
     pub target: Vec3,
     ang: f32,
+    pitch: f32,
     dir: i8,
+    /// The cell the camera is on, or heading to mid-step.
+    cell: [i32; 2],
+    /// Seconds a step or turn takes. Zero snaps instantly.
+    pub transition_time: f64,
+    pub turn_mode: TurnMode,
+    pub mode: CameraMode,
+    transition: Option<Transition>,
+    queue: VecDeque<Move>,
 }
 
 const PI: f32 = std::f32::consts::PI;
 const FORFPI: f32 = PI / 4.0;
 
+/// Eases in and out of a transition, `t` running from 0 to 1.
+fn smoothstep(t: f64) -> f32 {
+    let t = t.clamp(0.0, 1.0) as f32;
+    t * t * (3.0 - 2.0 * t)
+}
+
 impl Camera {
     pub fn new(spawn: [u32; 2]) -> Camera {
         let pos = Vec3::new(spawn[0] as f32, 0.0, spawn[1] as f32);
@@ -21,33 +115,179 @@ impl Camera {
             pos,
             target,
             ang,
+            pitch: 0.0,
             dir: 0,
+            cell: [spawn[0] as i32, spawn[1] as i32],
+            transition_time: DEFAULT_TRANSITION_TIME,
+            turn_mode: TurnMode::Eight,
+            mode: CameraMode::Grid,
+            transition: None,
+            queue: VecDeque::new(),
+        }
+    }
+
+    /// Queues a move to start once the current one finishes. Ignored in
+    /// free mode.
+    pub fn push_move(&mut self, m: Move) {
+        if self.mode == CameraMode::Free {
+            return;
+        }
+        if self.queue.len() < MAX_QUEUED_MOVES {
+            self.queue.push_back(m);
+        } else {
+            log::debug!("move queue full, dropping {:?}", m);
+        }
+    }
+
+    /// Advances the current transition by `dt` seconds, then starts the
+    /// next queued move if the camera is free. Returns what happened if that
+    /// move was a step.
+    pub fn update(&mut self, dt: f64, level: &Level) -> Option<WalkEvent> {
+        if let Some(t) = &mut self.transition {
+            t.elapsed += dt;
+            let done = t.elapsed >= self.transition_time;
+            let s = if done {
+                1.0
+            } else {
+                smoothstep(t.elapsed / self.transition_time)
+            };
+            self.pos = t.from_pos.lerp(t.to_pos, s);
+            self.ang = t.from_ang + (t.to_ang - t.from_ang) * s;
+            if done {
+                // back to the facing's own angle, which may differ by 2π
+                self.ang = self.dir as f32 * FORFPI;
+                self.transition = None;
+            }
+            self.look();
+            if !done {
+                return None;
+            }
+        }
+        match self.queue.pop_front()? {
+            Move::Turn(dir) => {
+                self.turn(dir);
+                None
+            }
+            Move::Walk(dir) => Some(self.walk(Self::step(self.dir, dir), level)),
+            Move::Strafe(side) => Some(self.walk(Self::step(self.dir + 2 * side, 1), level)),
         }
     }
 
-    pub fn turn(&mut self, dir: i8) {
-        // dir == 1 (+45°), dir == -1 (-45°)
-        self.dir += dir;
-        if self.dir > 7 {
-            self.dir = 0;
-        } else if self.dir < 0 {
-            self.dir = 7;
+    /// Points `target` along the current angle and pitch.
+    fn look(&mut self) {
+        let (sin, cos) = self.pitch.sin_cos();
+        self.target = self.pos + Vec3::new(self.ang.sin() * cos, sin, self.ang.cos() * cos);
+    }
+
+    /// Turns the camera by a mouse movement of `(dx, dy)` pixels. Only free
+    /// mode looks around.
+    pub fn look_around(&mut self, dx: f64, dy: f64) {
+        if self.mode != CameraMode::Free {
+            return;
         }
+        self.ang -= dx as f32 * MOUSE_SENSITIVITY;
+        self.pitch = (self.pitch - dy as f32 * MOUSE_SENSITIVITY).clamp(-MAX_PITCH, MAX_PITCH);
+        // the nearest facing, so doors and stairs are used the way the
+        // camera is looking
+        self.dir = (self.ang / FORFPI).round().rem_euclid(8.0) as i8;
+        self.look();
+    }
+
+    /// Moves the camera in free mode for `dt` seconds, `forward` and `left`
+    /// running from -1 to 1. Each axis is blocked separately, so walking
+    /// into a wall at an angle slides along it. Returns a `Moved` event when
+    /// the camera crosses into another cell.
+    pub fn free_move(
+        &mut self,
+        forward: f32,
+        left: f32,
+        dt: f64,
+        level: &Level,
+    ) -> Option<WalkEvent> {
+        let (sin, cos) = self.ang.sin_cos();
+        let mut motion = Vec3::new(sin, 0.0, cos) * forward + Vec3::new(cos, 0.0, -sin) * left;
+        if motion == Vec3::ZERO {
+            return None;
+        }
+        motion = motion.normalize() * FREE_SPEED * dt as f32;
+
+        let steps = (motion.length() / FREE_MAX_STEP).ceil().max(1.0);
+        let step = motion / steps;
+        for _ in 0..steps as u32 {
+            let x = self.pos.x + step.x;
+            if Self::fits(level, x, self.pos.z) {
+                self.pos.x = x;
+            }
+            let z = self.pos.z + step.z;
+            if Self::fits(level, self.pos.x, z) {
+                self.pos.z = z;
+            }
+        }
+        self.look();
+
+        let cell = [self.pos.x.round() as i32, self.pos.z.round() as i32];
+        if cell == self.cell {
+            return None;
+        }
+        self.cell = cell;
+        Some(WalkEvent::Moved { to: cell })
+    }
+
+    /// Whether a `FREE_RADIUS` camera at world `(x, z)` touches only
+    /// passable cells.
+    fn fits(level: &Level, x: f32, z: f32) -> bool {
+        let cells = |v: f32| (v - FREE_RADIUS).round() as i32..=(v + FREE_RADIUS).round() as i32;
+        cells(z).all(|cy| cells(x).all(|cx| level.is_passable(cx, cy)))
+    }
 
-        let new_ang = self.dir as f32 * FORFPI;
-        let x = new_ang.sin();
-        let y = new_ang.cos();
-        let target = self.pos + Vec3::new(x, 0.0, y);
-        self.target = target;
-        self.ang = new_ang;
+    fn start_transition(&mut self, to_pos: Vec3, to_ang: f32) {
+        self.transition = Some(Transition {
+            from_pos: self.pos,
+            to_pos,
+            from_ang: self.ang,
+            to_ang,
+            elapsed: 0.0,
+        });
+    }
+
+    fn turn(&mut self, dir: i8) {
+        // dir == 1 (left), dir == -1 (right), in 45° facings
+        let steps = match self.turn_mode {
+            TurnMode::Four => dir * 2,
+            TurnMode::Eight => dir,
+        };
+        self.dir = (self.dir + steps).rem_euclid(8);
+        // turning from the current angle keeps the short way round
+        self.start_transition(self.pos, self.ang + steps as f32 * FORFPI);
+
+        log::info!("ang: {}, dir: {}", self.dir as f32 * FORFPI, self.dir);
+    }
+
+    /// The level cell the camera is standing on, as (x, y). Mid-step this is
+    /// already the cell being stepped into.
+    pub fn cell(&self) -> [i32; 2] {
+        self.cell
+    }
 
-        log::info!("ang: {}, dir: {}", self.ang, self.dir);
+    /// Moves the camera onto another cell, keeping its facing. Any move in
+    /// progress or queued is dropped.
+    pub fn set_cell(&mut self, cell: [u32; 2]) {
+        self.cell = [cell[0] as i32, cell[1] as i32];
+        self.pos = Vec3::new(cell[0] as f32, self.pos.y, cell[1] as f32);
+        if self.mode == CameraMode::Grid {
+            self.ang = self.dir as f32 * FORFPI;
+        }
+        self.transition = None;
+        self.queue.clear();
+        self.look();
     }
 
-    pub fn walk(&mut self, dir: i8) {
+    /// The (x, y) cell offset of one step towards `facing` (any multiple of
+    /// 45°, wrapping), or of one step away from it when `dir` is -1.
+    fn step(facing: i8, dir: i8) -> (i8, i8) {
         let mut xdir = 0;
         let mut ydir = 0;
-        match self.dir {
+        match facing.rem_euclid(8) {
             0 => ydir = dir,
             1 => {
                 xdir = dir;
@@ -69,9 +309,21 @@ impl Camera {
                 ydir = dir
             }
             _ => {
-                log::error!("self.dir invalid: {}", self.dir);
+                log::error!("facing invalid: {}", facing);
             }
         }
+        (xdir, ydir)
+    }
+
+    /// The cell directly in front of the camera.
+    pub fn facing_cell(&self) -> [i32; 2] {
+        let [x, y] = self.cell();
+        let (xdir, ydir) = Self::step(self.dir, 1);
+        [x + xdir as i32, y + ydir as i32]
+    }
+
+    /// Tries to move one cell by the offset `(xdir, ydir)` from `step`.
+    fn walk(&mut self, (xdir, ydir): (i8, i8), level: &Level) -> WalkEvent {
         log::info!(
             "dir: {}, ang: {}, xdir: {}, ydir: {}",
             self.dir,
@@ -79,8 +331,181 @@ impl Camera {
             xdir,
             ydir
         );
-        let old_target = self.target - self.pos;
-        self.pos = self.pos + Vec3::new(xdir as f32, 0.0, ydir as f32); // y is z in RHC
-        self.target = self.pos + old_target;
+
+        let [x, y] = self.cell();
+        let target = [x + xdir as i32, y + ydir as i32];
+        let event = if xdir != 0 && ydir != 0 {
+            // diagonal: never squeeze between two solid orthogonal neighbours
+            let x_open = level.is_passable(target[0], y);
+            let y_open = level.is_passable(x, target[1]);
+            if !x_open && !y_open {
+                WalkEvent::Bumped { wall: target }
+            } else if level.is_passable(target[0], target[1]) {
+                WalkEvent::Moved { to: target }
+            } else if x_open {
+                WalkEvent::Deflected { to: [target[0], y] }
+            } else {
+                WalkEvent::Deflected { to: [x, target[1]] }
+            }
+        } else if level.is_passable(target[0], target[1]) {
+            WalkEvent::Moved { to: target }
+        } else {
+            WalkEvent::Bumped { wall: target }
+        };
+
+        match event {
+            WalkEvent::Moved { to } | WalkEvent::Deflected { to } => {
+                self.cell = to;
+                let to_pos = Vec3::new(to[0] as f32, self.pos.y, to[1] as f32); // y is z in RHC
+                self.start_transition(to_pos, self.ang);
+            }
+            WalkEvent::Bumped { .. } => (),
+        }
+        event
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::level::tiles::test_registry;
+
+    fn map(text: &str) -> Level {
+        Level::from_ascii(text, test_registry()).unwrap()
+    }
+
+    /// A camera on the level's spawn whose moves snap instantly.
+    fn snapping(level: &Level) -> Camera {
+        let mut camera = Camera::new(level.spawn);
+        camera.transition_time = 0.0;
+        camera
+    }
+
+    /// Runs one queued move to the end, returning what the step did.
+    fn run(camera: &mut Camera, m: Move, level: &Level) -> Option<WalkEvent> {
+        camera.push_move(m);
+        let event = camera.update(0.0, level);
+        camera.update(0.0, level);
+        event
+    }
+
+    #[test]
+    fn walls_block_steps() {
+        let level = map("#####\n#@..#\n#####\n");
+        let mut camera = snapping(&level);
+        run(&mut camera, Move::Turn(1), &level);
+        run(&mut camera, Move::Turn(1), &level);
+        // facing +x along the corridor
+        assert_eq!(run(&mut camera, Move::Walk(1), &level), Some(WalkEvent::Moved { to: [2, 1] }));
+        assert_eq!(run(&mut camera, Move::Walk(1), &level), Some(WalkEvent::Moved { to: [3, 1] }));
+        let event = run(&mut camera, Move::Walk(1), &level);
+        assert_eq!(event, Some(WalkEvent::Bumped { wall: [4, 1] }));
+        assert_eq!(camera.cell(), [3, 1]);
+        assert_eq!(camera.pos, Vec3::new(3.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn diagonal_between_two_walls_bumps() {
+        let level = map("#####\n#@#.#\n##..#\n#####\n");
+        let mut camera = snapping(&level);
+        run(&mut camera, Move::Turn(1), &level);
+        let event = run(&mut camera, Move::Walk(1), &level);
+        assert_eq!(event, Some(WalkEvent::Bumped { wall: [2, 2] }));
+        assert_eq!(camera.cell(), [1, 1]);
+    }
+
+    #[test]
+    fn half_open_diagonal_deflects() {
+        let level = map("#####\n#@..#\n###.#\n#####\n");
+        let mut camera = snapping(&level);
+        run(&mut camera, Move::Turn(1), &level);
+        let event = run(&mut camera, Move::Walk(1), &level);
+        assert_eq!(event, Some(WalkEvent::Deflected { to: [2, 1] }));
+        assert_eq!(camera.cell(), [2, 1]);
+
+        // open all the way, the diagonal step goes through
+        let level = map("####\n#@.#\n#..#\n####\n");
+        let mut camera = snapping(&level);
+        run(&mut camera, Move::Turn(1), &level);
+        assert_eq!(run(&mut camera, Move::Walk(1), &level), Some(WalkEvent::Moved { to: [2, 2] }));
+    }
+
+    #[test]
+    fn strafing_keeps_the_facing() {
+        let level = map("#####\n#.@.#\n#####\n");
+        let mut camera = snapping(&level);
+        // facing +y, so the camera's left is +x
+        let event = run(&mut camera, Move::Strafe(1), &level);
+        assert_eq!(event, Some(WalkEvent::Moved { to: [3, 1] }));
+        assert_eq!(camera.facing_cell(), [3, 2]);
+        run(&mut camera, Move::Strafe(-1), &level);
+        let event = run(&mut camera, Move::Strafe(-1), &level);
+        assert_eq!(event, Some(WalkEvent::Moved { to: [1, 1] }));
+        assert_eq!(camera.facing_cell(), [1, 2]);
+    }
+
+    #[test]
+    fn four_way_turns_stay_on_the_grid() {
+        let level = map("###\n#@#\n###\n");
+        let mut camera = snapping(&level);
+        camera.turn_mode = TurnMode::Four;
+        run(&mut camera, Move::Turn(1), &level);
+        assert_eq!(camera.facing_cell(), [2, 1]);
+        run(&mut camera, Move::Turn(-1), &level);
+        run(&mut camera, Move::Turn(-1), &level);
+        assert_eq!(camera.facing_cell(), [0, 1]);
+        run(&mut camera, Move::Turn(-1), &level);
+        assert_eq!(camera.facing_cell(), [1, 0]);
+    }
+
+    #[test]
+    fn extra_moves_are_dropped_and_steps_end_on_the_cell() {
+        let level = map("#######\n#@....#\n#######\n");
+        let mut camera = Camera::new(level.spawn);
+        camera.turn_mode = TurnMode::Four;
+        camera.push_move(Move::Turn(1));
+        for _ in 0..3 {
+            camera.push_move(Move::Walk(1));
+        }
+        // the turn and one step fit in the queue; the rest are dropped
+        for _ in 0..10 {
+            camera.update(0.1, &level);
+        }
+        assert_eq!(camera.cell(), [2, 1]);
+        assert_eq!(camera.pos, Vec3::new(2.0, 0.0, 1.0));
+        assert_eq!(camera.ang, 2.0 * FORFPI);
+    }
+
+    /// A camera on the level's spawn in free mode, facing +y.
+    fn free(level: &Level) -> Camera {
+        let mut camera = Camera::new(level.spawn);
+        camera.mode = CameraMode::Free;
+        camera
+    }
+
+    #[test]
+    fn free_movement_slides_along_walls() {
+        let level = map("######\n#@...#\n######\n");
+        let mut camera = free(&level);
+        // forward runs into the wall, left runs along the corridor
+        let event = camera.free_move(1.0, 1.0, 0.5, &level);
+        assert!(camera.pos.x > 1.5, "{:?}", camera.pos);
+        assert!(camera.pos.z < 1.5, "{:?}", camera.pos);
+        assert_eq!(event, Some(WalkEvent::Moved { to: [2, 1] }));
+    }
+
+    #[test]
+    fn slow_frames_do_not_skip_walls() {
+        let level = map("###\n#@#\n#.#\n###\n#.#\n###\n");
+        let mut camera = free(&level);
+        // a one second frame covers three cells, landing past the wall at y = 3
+        camera.free_move(1.0, 0.0, 1.0, &level);
+        assert_eq!(camera.cell(), [1, 2]);
+        assert!(camera.pos.z < 2.5, "{:?}", camera.pos);
+
+        let level = map("###\n#@#\n#+#\n#.#\n###\n");
+        let mut camera = free(&level);
+        camera.free_move(1.0, 0.0, 0.7, &level);
+        assert_eq!(camera.cell(), [1, 1]);
     }
 }
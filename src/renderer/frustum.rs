@@ -0,0 +1,28 @@
+use glam::{Mat4, Vec3, Vec4};
+
+/// The six planes bounding what a camera can see, facing inwards.
+pub struct Frustum {
+    planes: [Vec4; 6],
+}
+
+impl Frustum {
+    /// Extracts the planes from a combined projection and view matrix.
+    pub fn from_matrix(m: Mat4) -> Self {
+        let (x, y, z, w) = (m.row(0), m.row(1), m.row(2), m.row(3));
+        let mut planes = [w + x, w - x, w + y, w - y, w + z, w - z];
+        for plane in planes.iter_mut() {
+            *plane /= plane.truncate().length();
+        }
+        Self { planes }
+    }
+
+    /// Whether any part of the box from `min` to `max` can be inside. Boxes
+    /// near a corner of the frustum may pass without being visible.
+    pub fn intersects_box(&self, min: Vec3, max: Vec3) -> bool {
+        self.planes.iter().all(|plane| {
+            // the corner furthest along the plane's normal
+            let corner = Vec3::select(plane.truncate().cmpge(Vec3::ZERO), max, min);
+            plane.truncate().dot(corner) + plane.w >= 0.0
+        })
+    }
+}
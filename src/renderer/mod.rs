@@ -6,5 +6,6 @@ pub(crate) mod texture;
 mod vertex_array;
 pub mod camera;
 mod frame_buffer;
+mod frustum;
 
-pub use renderer::Renderer;
\ No newline at end of file
+pub use renderer::{PointLight, Projection, Renderer};
\ No newline at end of file
@@ -1,6 +1,6 @@
 use crate::renderer::shader::{Shader, ShaderError};
 use gl::types::*;
-use glam::Mat4;
+use glam::{Mat4, Vec3};
 use std::ffi::CString;
 
 pub struct ShaderProgram {
@@ -59,12 +59,35 @@ impl ShaderProgram {
         Ok(gl::GetAttribLocation(self.id, attrib.as_ptr()) as GLuint)
     }
 
-    // pub unsafe fn set_int_uniform(&self, name: &str, value: i32) -> Result<(), ShaderError> {
-    //     self.apply();
-    //     let uniform = CString::new(name)?;
-    //     gl::Uniform1i(gl::GetUniformLocation(self.id, uniform.as_ptr()), value);
-    //     Ok(())
-    // }
+    pub unsafe fn set_int_uniform(&self, name: &str, value: i32) -> Result<(), ShaderError> {
+        // assumes apply has been called, like set_mat4_uniform
+        let uniform = CString::new(name)?;
+        gl::Uniform1i(gl::GetUniformLocation(self.id, uniform.as_ptr()), value);
+        Ok(())
+    }
+
+    pub unsafe fn set_vec3_array_uniform(
+        &self,
+        name: &str,
+        values: &[Vec3],
+    ) -> Result<(), ShaderError> {
+        let uniform = CString::new(name)?;
+        let location_pos = gl::GetUniformLocation(self.id, uniform.as_ptr());
+        let flat: Vec<f32> = values.iter().flat_map(|v| v.to_array()).collect();
+        gl::Uniform3fv(location_pos, values.len() as GLsizei, flat.as_ptr());
+        Ok(())
+    }
+
+    pub unsafe fn set_float_array_uniform(
+        &self,
+        name: &str,
+        values: &[f32],
+    ) -> Result<(), ShaderError> {
+        let uniform = CString::new(name)?;
+        let location_pos = gl::GetUniformLocation(self.id, uniform.as_ptr());
+        gl::Uniform1fv(location_pos, values.len() as GLsizei, values.as_ptr());
+        Ok(())
+    }
 
     pub unsafe fn set_mat4_uniform(&self, name: &str, value: Mat4) -> Result<(), ShaderError> {
         // self.apply(); this function assumes you've called apply already
@@ -1,16 +1,17 @@
-use crate::models::cube::Cube;
+use crate::models::level_mesh::ChunkMesh;
 use crate::renderer::buffer::Buffer;
 use crate::renderer::program::ShaderProgram;
 use crate::renderer::shader::{Shader, ShaderError};
 use crate::renderer::vertex_array::VertexArray;
 use crate::ui::ui::UserInterface;
-use gl::types::GLint;
+use gl::types::{GLint, GLuint};
 use glam::{Mat4, Vec3};
 use image::ImageError;
 use std::ptr;
 use thiserror::Error;
 
 use super::camera::Camera;
+use super::frustum::Frustum;
 // use super::texture::{Texture, UITexture};
 
 // const VERTEX_SHADER_SOURCE: &str = r#"
@@ -50,27 +51,43 @@ layout(location = 0) in vec3 position;
 layout(location = 1) in vec3 vertexUV;
 
 out vec3 UV;
+out vec3 worldPosition;
 
 uniform mat4 transform;
 
 void main() {
     gl_Position = transform * vec4(position, 1.0f);
     UV = vertexUV;
+    worldPosition = position;
 }
 "#;
 
 const FRAGMENT_SHADER_SOURCE: &str = r#"
 #version 330
 
+#define MAX_LIGHTS 16
+
 in vec3 UV;
+in vec3 worldPosition;
 
 out vec4 color;
 
 uniform sampler2DArray t2da;
 // uniform sampler2D uiTexture;
 
+uniform int lightCount;
+uniform vec3 lightPositions[MAX_LIGHTS];
+uniform vec3 lightColors[MAX_LIGHTS];
+uniform float lightRadii[MAX_LIGHTS];
+
 void main() {
-    color = texture(t2da, UV);
+    // lights brighten the scene, unlit areas keep their texture colour
+    vec3 light = vec3(1.0);
+    for (int i = 0; i < lightCount; i++) {
+        float d = distance(worldPosition, lightPositions[i]);
+        light += lightColors[i] * max(0.0, 1.0 - d / lightRadii[i]);
+    }
+    color = texture(t2da, UV) * vec4(light, 1.0);
 }
 "#;
 
@@ -108,22 +125,6 @@ void main() {
 }
 "#;
 
-#[rustfmt::skip]
-const CUBE_INDICES: [i32; 36] = [
-     0,  1,  2,
-     2,  3,  0,
-     4,  5,  6,
-     6,  7,  4,
-     8,  9, 10,
-    10, 11,  8,
-    12, 13, 14,
-    14, 15, 12,
-    16, 17, 18,
-    18, 19, 16,
-    20, 21, 22,
-    22, 23, 20,
-];
-
 // #[rustfmt::skip]
 // const UI_VERTICES: [f32; 16] = [
 // 	 1.0,  1.0,	1.0,  1.0,
@@ -154,6 +155,39 @@ const UI_INDICES: [i32; 6] = [
     1, 2, 3
 ];
 
+/// Lights uploaded to the level shader per frame; must match the shader.
+const MAX_LIGHTS: usize = 16;
+
+/// A light in world space, e.g. from a torch tile.
+#[derive(Debug, Clone, Copy)]
+pub struct PointLight {
+    pub position: Vec3,
+    pub color: Vec3,
+    /// Distance at which the light has faded out.
+    pub radius: f32,
+}
+
+/// How the level is projected onto the screen. The aspect ratio comes from
+/// the window.
+#[derive(Debug, Clone, Copy)]
+pub struct Projection {
+    /// Vertical field of view, in degrees.
+    pub fov: f32,
+    /// Distances to the near and far clipping planes, in cells.
+    pub near: f32,
+    pub far: f32,
+}
+
+impl Default for Projection {
+    fn default() -> Self {
+        Self {
+            fov: 45.0,
+            near: 0.1,
+            far: 2000.0,
+        }
+    }
+}
+
 #[derive(Debug, Error)]
 pub enum RendererInitError {
     #[error{"{0}"}]
@@ -164,74 +198,76 @@ pub enum RendererInitError {
 
 pub struct Renderer {
     program: ShaderProgram,
-    _vertex_buffer: Buffer,
-    _index_buffer: Buffer,
-    vertex_array: VertexArray,
+    pos_attrib: GLuint,
+    texture_attrib: GLuint,
+    chunks: Vec<Chunk>,
     ui_program: ShaderProgram,
     _ui_vertex_buffer: Buffer,
     _ui_index_buffer: Buffer,
     ui_vertex_array: VertexArray,
     angle: f32,
-    total_length: i32,
+    lights: Vec<PointLight>,
     ui: UserInterface,
+    projection: Projection,
+    /// Framebuffer size in pixels.
+    size: [u32; 2],
+}
+
+/// A chunk of level geometry on the GPU, with the box around it for
+/// culling.
+struct Chunk {
+    vertex_array: VertexArray,
+    vertex_buffer: Buffer,
+    index_buffer: Buffer,
+    index_count: i32,
+    min: Vec3,
+    max: Vec3,
 }
 
-// todo: put this in the cube impl
-fn get_indices(index: i32) -> [i32; 36] {
-    let mut new_indices: [i32; 36] = [0; 36];
-    let mut i = 0;
-    while i < 36 {
-        new_indices[i] = CUBE_INDICES[i] + (index * 24);
-        i += 1;
+// gives the chunk its own vertex array, so the index buffer binding and
+// attribute layout come with it when drawing
+unsafe fn upload_chunk(chunk: &ChunkMesh, pos_attrib: GLuint, texture_attrib: GLuint) -> Chunk {
+    let vertex_array = VertexArray::new();
+    vertex_array.bind();
+
+    let mut vertex_buffer = Buffer::new(gl::ARRAY_BUFFER);
+    let mut index_buffer = Buffer::new(gl::ELEMENT_ARRAY_BUFFER);
+    vertex_buffer.set_data(chunk.mesh.vertices(), gl::DYNAMIC_DRAW);
+    index_buffer.set_data(chunk.mesh.indices(), gl::DYNAMIC_DRAW);
+
+    let stride = 6 * std::mem::size_of::<f32>() as GLint;
+    vertex_array.set_attribute(pos_attrib, 3, 0, stride);
+    vertex_array.set_attribute(texture_attrib, 3, 3, stride);
+
+    Chunk {
+        vertex_array,
+        vertex_buffer,
+        index_buffer,
+        index_count: chunk.mesh.indices().len() as i32,
+        min: chunk.min,
+        max: chunk.max,
     }
-    log::debug!("new_indices: {}", format!("{:?}", new_indices));
-    return new_indices;
 }
 
 impl Renderer {
-    pub fn new(cubes: Vec<Cube>, ui: UserInterface) -> Result<Self, RendererInitError> {
+    pub fn new(
+        chunks: &[ChunkMesh],
+        ui: UserInterface,
+        projection: Projection,
+        size: [u32; 2],
+    ) -> Result<Self, RendererInitError> {
         unsafe {
             // Level shader program and buffers
             let vertex_shader = Shader::new(VERTEX_SHADER_SOURCE, gl::VERTEX_SHADER)?;
             let fragment_shader = Shader::new(FRAGMENT_SHADER_SOURCE, gl::FRAGMENT_SHADER)?;
             let program = ShaderProgram::new(&[vertex_shader, fragment_shader])?;
 
-            let vertex_array = VertexArray::new();
-            vertex_array.bind();
-
-            let vertex_buffer = Buffer::new(gl::ARRAY_BUFFER);
-            let index_buffer = Buffer::new(gl::ELEMENT_ARRAY_BUFFER);
-
-            let mut verts: Vec<f32> = Vec::new();
-            let mut indices: Vec<i32> = Vec::new();
-
-            let mut i = 0;
-            for c in cubes.iter() {
-                log::debug!("Got: {}", c.loc());
-                verts.extend_from_slice(&c.geom());
-                indices.extend_from_slice(&get_indices(i));
-                i += 1;
-            }
-            log::debug!("verts: {}", format!("{:?}", verts));
-            log::debug!("indices: {}", format!("{:?}", indices));
-
-            vertex_buffer.set_data(&verts, gl::STATIC_DRAW);
-            index_buffer.set_data(&indices, gl::STATIC_DRAW);
-
             let pos_attrib = program.get_attrib_location("position")?;
-            vertex_array.set_attribute(pos_attrib, 3, 0, 6 * std::mem::size_of::<f32>() as GLint);
-
             let texture_attrib = program.get_attrib_location("vertexUV")?;
-            vertex_array.set_attribute(
-                texture_attrib,
-                3,
-                3,
-                6 * std::mem::size_of::<f32>() as GLint,
-            );
-
-            // vertex_array.unbind();
-
-            let total_length = (cubes.len() * 72) as i32;
+            let chunks = chunks
+                .iter()
+                .map(|chunk| upload_chunk(chunk, pos_attrib, texture_attrib))
+                .collect();
 
             // UI shader program and buffers
             let ui_vertex_shader = Shader::new(UI_VERTEX_SHADER_SOURCE, gl::VERTEX_SHADER)?;
@@ -241,8 +277,8 @@ impl Renderer {
             let ui_vertex_array = VertexArray::new();
             ui_vertex_array.bind();
 
-            let ui_vertex_buffer = Buffer::new(gl::ARRAY_BUFFER);
-            let ui_index_buffer = Buffer::new(gl::ELEMENT_ARRAY_BUFFER);
+            let mut ui_vertex_buffer = Buffer::new(gl::ARRAY_BUFFER);
+            let mut ui_index_buffer = Buffer::new(gl::ELEMENT_ARRAY_BUFFER);
 
             ui_vertex_buffer.set_data(&UI_VERTICES, gl::STATIC_DRAW);
             ui_index_buffer.set_data(&UI_INDICES, gl::STATIC_DRAW);
@@ -258,29 +294,85 @@ impl Renderer {
 
             gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
             gl::Enable(gl::BLEND);
+            gl::Viewport(0, 0, size[0] as i32, size[1] as i32);
 
             log::info!("returning renderer");
 
             Ok(Self {
                 program,
-                _vertex_buffer: vertex_buffer,
-                _index_buffer: index_buffer,
-                vertex_array,
+                pos_attrib,
+                texture_attrib,
+                chunks,
                 ui_program,
                 _ui_vertex_buffer: ui_vertex_buffer,
                 _ui_index_buffer: ui_index_buffer,
                 ui_vertex_array,
                 angle: 0.0,
-                total_length,
-                ui
+                lights: Vec::new(),
+                ui,
+                projection,
+                size,
             })
         }
     }
 
+    /// Replaces all of the level geometry, e.g. on arriving at a new floor.
+    pub fn set_chunks(&mut self, chunks: &[ChunkMesh]) {
+        unsafe {
+            self.chunks = chunks
+                .iter()
+                .map(|chunk| upload_chunk(chunk, self.pos_attrib, self.texture_attrib))
+                .collect();
+        }
+    }
+
+    /// Replaces some of the chunks, given by their index in the full list,
+    /// e.g. after a door opens. Their buffers are reused where they fit.
+    pub fn update_chunks(&mut self, chunks: &[(usize, ChunkMesh)]) {
+        for (index, mesh) in chunks {
+            let chunk = match self.chunks.get_mut(*index) {
+                Some(chunk) => chunk,
+                None => {
+                    log::warn!("no chunk {} to update", index);
+                    continue;
+                }
+            };
+            unsafe {
+                // the index buffer binding is part of the vertex array state
+                chunk.vertex_array.bind();
+                chunk.vertex_buffer.stream_data(mesh.mesh.vertices(), gl::DYNAMIC_DRAW);
+                chunk.index_buffer.stream_data(mesh.mesh.indices(), gl::DYNAMIC_DRAW);
+            }
+            chunk.index_count = mesh.mesh.indices().len() as i32;
+        }
+    }
+
+    /// Follows a change in framebuffer size: the viewport, the projection's
+    /// aspect ratio and the UI all match the new size. A minimised window
+    /// reports zero and is ignored.
+    pub fn resize(&mut self, size: [u32; 2]) {
+        if size[0] == 0 || size[1] == 0 || size == self.size {
+            return;
+        }
+        self.size = size;
+        unsafe {
+            gl::Viewport(0, 0, size[0] as i32, size[1] as i32);
+        }
+        self.ui.resize(size);
+    }
+
+    /// Replaces the level's lights. Only the ones nearest the camera are
+    /// drawn.
+    pub fn set_lights(&mut self, lights: &[PointLight]) {
+        self.lights = lights.to_vec();
+    }
+
     pub fn draw(&mut self, cam: &Camera) {
         let model = Mat4::from_rotation_x(self.angle);
         let view = Mat4::look_at_rh(cam.pos, cam.target, Vec3::new(0.0, 1.0, 0.0));
-        let projection = Mat4::perspective_rh_gl(45.0f32.to_radians(), 1024.0 / 768.0, 0.1, 2000.0);
+        let Projection { fov, near, far } = self.projection;
+        let aspect = self.size[0] as f32 / self.size[1] as f32;
+        let projection = Mat4::perspective_rh_gl(fov.to_radians(), aspect, near, far);
         let transform = projection * view * model;
 
         unsafe {
@@ -294,16 +386,24 @@ impl Renderer {
 
             // render level
             self.program.apply();
-            self.vertex_array.bind();
             let _ = self.program.set_mat4_uniform("transform", transform);
+            self.upload_lights(cam.pos);
             gl::Enable(gl::DEPTH_TEST);
             gl::DepthFunc(gl::LESS);
-            gl::DrawElements(
-                gl::TRIANGLES,
-                self.total_length,
-                gl::UNSIGNED_INT,
-                ptr::null(),
-            );
+            // chunk boxes are in model space, like the transform's input
+            let frustum = Frustum::from_matrix(transform);
+            for chunk in &self.chunks {
+                if chunk.index_count == 0 || !frustum.intersects_box(chunk.min, chunk.max) {
+                    continue;
+                }
+                chunk.vertex_array.bind();
+                gl::DrawElements(
+                    gl::TRIANGLES,
+                    chunk.index_count,
+                    gl::UNSIGNED_INT,
+                    ptr::null(),
+                );
+            }
 
             // self.vertex_array.unbind();
 
@@ -317,4 +417,21 @@ impl Renderer {
             gl::DrawElements(gl::TRIANGLES, 8, gl::UNSIGNED_INT, ptr::null());
         }
     }
+
+    unsafe fn upload_lights(&mut self, eye: Vec3) {
+        self.lights.sort_by(|a, b| {
+            let (da, db) = (a.position.distance_squared(eye), b.position.distance_squared(eye));
+            da.total_cmp(&db)
+        });
+        let nearest = &self.lights[..self.lights.len().min(MAX_LIGHTS)];
+        let positions: Vec<Vec3> = nearest.iter().map(|l| l.position).collect();
+        let colors: Vec<Vec3> = nearest.iter().map(|l| l.color).collect();
+        let radii: Vec<f32> = nearest.iter().map(|l| l.radius).collect();
+        let _ = self.program.set_int_uniform("lightCount", nearest.len() as i32);
+        if !nearest.is_empty() {
+            let _ = self.program.set_vec3_array_uniform("lightPositions", &positions);
+            let _ = self.program.set_vec3_array_uniform("lightColors", &colors);
+            let _ = self.program.set_float_array_uniform("lightRadii", &radii);
+        }
+    }
 }
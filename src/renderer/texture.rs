@@ -1,7 +1,36 @@
 use gl::types::*;
-use image::{EncodableLayout, ImageError, RgbaImage};
-use std::{env, fs::read_dir, path::Path};
-use walkdir::WalkDir;
+use image::{imageops, EncodableLayout, ImageError, RgbaImage};
+use std::collections::HashMap;
+use std::fs::read_dir;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::assets::AssetError;
+
+/// The image files in `path`, sorted by file name. This order is what makes
+/// tile layers the same on every machine, whatever order the filesystem
+/// lists them in.
+pub(crate) fn tile_files(path: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for entry in read_dir(path)? {
+        let entry = entry?;
+        if entry.path().is_file() {
+            files.push(entry.path());
+        }
+    }
+    files.sort_by(|a, b| a.file_name().cmp(&b.file_name()));
+    Ok(files)
+}
+
+fn open_image(path: &Path) -> Result<RgbaImage, AssetError> {
+    match image::open(path) {
+        Ok(img) => Ok(img.into_rgba8()),
+        Err(source) => Err(AssetError::Image {
+            path: path.display().to_string(),
+            source,
+        }),
+    }
+}
 
 pub struct Texture {
     pub id: GLuint,
@@ -75,58 +104,136 @@ impl TextureArray {
         Self { id }
     }
 
-    pub unsafe fn load(&self, path: &Path) {
-        self.bind();
-
-        let num_paths = read_dir(path).unwrap().count();
+    /// Loads every image in `path` into the array, one layer each, in file
+    /// name order. The images can be any size as long as they all match.
+    /// Returns the layer of each image by file name.
+    pub unsafe fn load(&self, path: &Path) -> Result<HashMap<String, u32>, AssetError> {
+        let files = tile_files(path).map_err(|source| AssetError::Io {
+            path: path.display().to_string(),
+            source,
+        })?;
+        let mut size = None;
+        let mut pixels = Vec::new();
+        let mut layers = HashMap::new();
+
+        for (layer, file) in files.iter().enumerate() {
+            log::info!("loading tile: {}", file.display());
+            let img = open_image(file)?;
+            let expected = *size.get_or_insert(img.dimensions());
+            if img.dimensions() != expected {
+                return Err(AssetError::SizeMismatch {
+                    path: file.display().to_string(),
+                    width: img.width(),
+                    height: img.height(),
+                    expected_width: expected.0,
+                    expected_height: expected.1,
+                });
+            }
+            pixels.extend_from_slice(img.as_raw());
+            let name = file.file_name().unwrap_or_default().to_string_lossy();
+            layers.insert(name.into_owned(), layer as u32);
+        }
 
-        gl::TexStorage3D(gl::TEXTURE_2D_ARRAY, 1, gl::RGBA8, 16, 16, 91);
+        let (width, height) =
+            size.ok_or_else(|| AssetError::NoImages(path.display().to_string()))?;
+        self.upload(width, height, files.len() as u32, &pixels)?;
+        Ok(layers)
+    }
 
-        let mut all_img_data: Vec<u8> = Vec::new();
+    /// Slices a tilesheet into `tile_size` tiles, left to right and top to
+    /// bottom, one layer each. Returns the layer of each tile by name, which
+    /// is the sheet's file name and the tile's index, e.g. `tiles.png#40`.
+    pub unsafe fn load_sheet(
+        &self,
+        path: &Path,
+        tile_size: [u32; 2],
+    ) -> Result<HashMap<String, u32>, AssetError> {
+        let img = open_image(path)?;
+        let [tile_width, tile_height] = tile_size;
+        if tile_width == 0
+            || tile_height == 0
+            || img.width() % tile_width != 0
+            || img.height() % tile_height != 0
+        {
+            return Err(AssetError::BadSheet {
+                path: path.display().to_string(),
+                width: img.width(),
+                height: img.height(),
+                tile_width,
+                tile_height,
+            });
+        }
 
-        let dir = match env::current_dir() {
-            Ok(it) => it,
-            Err(e) => {
-                log::error!("{}", e);
-                return;
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let (columns, rows) = (img.width() / tile_width, img.height() / tile_height);
+        let mut pixels = Vec::new();
+        let mut layers = HashMap::new();
+        for row in 0..rows {
+            for column in 0..columns {
+                let x = column * tile_width;
+                let y = row * tile_height;
+                let tile = imageops::crop_imm(&img, x, y, tile_width, tile_height).to_image();
+                pixels.extend_from_slice(tile.as_raw());
+                let layer = row * columns + column;
+                layers.insert(format!("{}#{}", name, layer), layer);
             }
-        };
-
-        log::info!("current_dir: {}", dir.display());
+        }
+        log::info!(
+            "sliced {} into {} {}x{} tiles",
+            path.display(),
+            layers.len(),
+            tile_width,
+            tile_height
+        );
+        self.upload(tile_width, tile_height, rows * columns, &pixels)?;
+        Ok(layers)
+    }
 
-        for entry in WalkDir::new(path).into_iter().filter_map(|e| e.ok()) {
-            if !entry.path().is_file() {
-                continue;
-            }
-            log::info!("loading tile: {}", entry.path().display());
-            let img_result = image::open(entry.path());
-            let img = match img_result {
-                Ok(it) => it,
-                Err(e) => {
-                    log::error!("could not load tile image: {}", e);
-                    return;
-                }
-            };
-            let mut img_data = img.into_rgba8().into_vec();
-            all_img_data.append(&mut img_data);
+    /// Allocates the array and fills it with `count` layers of tightly
+    /// packed RGBA pixels.
+    unsafe fn upload(
+        &self,
+        width: u32,
+        height: u32,
+        count: u32,
+        pixels: &[u8],
+    ) -> Result<(), AssetError> {
+        let mut max_layers: GLint = 0;
+        gl::GetIntegerv(gl::MAX_ARRAY_TEXTURE_LAYERS, &mut max_layers);
+        if count > max_layers as u32 {
+            return Err(AssetError::TooManyLayers {
+                count,
+                max: max_layers as u32,
+            });
         }
 
+        self.bind();
+        gl::TexStorage3D(
+            gl::TEXTURE_2D_ARRAY,
+            1,
+            gl::RGBA8,
+            width as i32,
+            height as i32,
+            count as i32,
+        );
         gl::TexSubImage3D(
             gl::TEXTURE_2D_ARRAY,
             0,
             0,
             0,
             0,
-            16,
-            16,
-            num_paths as i32,
+            width as i32,
+            height as i32,
+            count as i32,
             gl::RGBA,
             gl::UNSIGNED_BYTE,
-            all_img_data.as_bytes().as_ptr() as *const _,
+            pixels.as_ptr() as *const _,
         );
+        log::info!("loaded {} {}x{} texture layers", count, width, height);
 
         self.set_filtering(gl::NEAREST);
         self.set_wrapping(gl::REPEAT);
+        Ok(())
     }
 
     pub unsafe fn set_wrapping(&self, mode: GLuint) {
@@ -218,3 +325,27 @@ impl Drop for UITexture {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn tile_files_are_in_file_name_order() {
+        let dir = std::env::temp_dir().join(format!("tile_files_{}", std::process::id()));
+        fs::create_dir_all(dir.join("subfolder.png")).unwrap();
+        // created out of order, so directory order can't line up by chance
+        for name in ["tile_0010.png", "tile_0002.png", "tile_0001.png", "a.png"] {
+            fs::write(dir.join(name), b"").unwrap();
+        }
+        let names: Vec<_> = tile_files(&dir)
+            .unwrap()
+            .iter()
+            .map(|f| f.file_name().unwrap().to_str().unwrap().to_string())
+            .collect();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(names, ["a.png", "tile_0001.png", "tile_0002.png", "tile_0010.png"]);
+    }
+}
@@ -1,24 +1,40 @@
 use rusttype::{point, Font, Scale};
 use image::{Rgba, RgbaImage};
 
+use crate::assets::AssetError;
 use crate::renderer::texture::UITexture;
 
+const FONT_PATH: &str = "assets/fonts/ps.ttf";
+
 pub struct UserInterface {
     pub ui_texture: UITexture,
+    font: Font<'static>,
 }
 
 impl UserInterface {
-    pub fn new(window_size: [u32; 2]) -> Self {
+    pub fn new(window_size: [u32; 2]) -> Result<Self, AssetError> {
         let ui = unsafe { UITexture::new() };
         log::info!("created UI texture with id {}", ui.id);
-        let mut img = RgbaImage::new(window_size[0], window_size[1]);
         let font: &[u8] = include_bytes!("../../assets/fonts/ps.ttf") as &[u8];
-        let font = Font::try_from_bytes(font).unwrap();
+        let font = Font::try_from_bytes(font)
+            .ok_or_else(|| AssetError::BadFont(FONT_PATH.to_string()))?;
+        let ui = UserInterface {
+            ui_texture: ui,
+            font,
+        };
+        ui.resize(window_size);
+        Ok(ui)
+    }
+
+    /// Redraws the UI at a new window size, reallocating its texture.
+    pub fn resize(&self, window_size: [u32; 2]) {
+        let mut img = RgbaImage::new(window_size[0], window_size[1]);
         let scale = Scale::uniform(42.0);
         let text = "♥";
         let colour = (255, 0, 0);
-        let v_metrics = font.v_metrics(scale);
-        let glyphs: Vec<_> = font
+        let v_metrics = self.font.v_metrics(scale);
+        let glyphs: Vec<_> = self
+            .font
             .layout(
                 text,
                 scale,
@@ -29,10 +45,16 @@ impl UserInterface {
             if let Some(bounding_box) = glyph.pixel_bounding_box() {
                 // Draw the glyph into the image per-pixel by using the draw closure
                 glyph.draw(|x, y, v| {
+                    // Offset the position by the glyph bounding box
+                    let x = x as i32 + bounding_box.min.x;
+                    let y = y as i32 + bounding_box.min.y;
+                    // a small window can leave the glyph partly off screen
+                    if x < 0 || y < 0 || x >= img.width() as i32 || y >= img.height() as i32 {
+                        return;
+                    }
                     img.put_pixel(
-                        // Offset the position by the glyph bounding box
-                        x + bounding_box.min.x as u32,
-                        y + bounding_box.min.y as u32,
+                        x as u32,
+                        y as u32,
                         // Turn the coverage into an alpha value
                         Rgba([colour.0, colour.1, colour.2, (v * 255.0) as u8]),
                     )
@@ -52,9 +74,7 @@ impl UserInterface {
         // };
 
         unsafe {
-            ui.load(&img);
+            self.ui_texture.load(&img);
         };
-
-        UserInterface { ui_texture: ui }
     }
 }